@@ -0,0 +1,111 @@
+//! Times loading a synthetic blog with one media file per post, to check that resolving the
+//! media files scales to large blogs, then times finding each post's file with the [BlogDir]
+//! index against the linear scan it replaced
+//!
+//! Run with `cargo bench --bench large_blog -- <number of posts>`, defaulting to 50,000 posts
+
+#[path = "../src/blog_dir.rs"]
+#[allow(dead_code)]
+mod blog_dir;
+
+use blog_dir::BlogDir;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+const DEFAULT_POSTS: usize = 50_000;
+const BLOG_NAME: &str = "large";
+
+fn main() -> std::io::Result<()> {
+    let posts = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_POSTS);
+    let base_dir = std::env::temp_dir().join(format!("tumbl-three-viewer-bench-{}", posts));
+    let blog_dir = base_dir.join(BLOG_NAME);
+    if !blog_dir.join("images.txt").is_file() {
+        println!(
+            "Creating a blog with {} posts in {}",
+            posts,
+            blog_dir.display()
+        );
+        create_blog(&blog_dir, posts)?;
+    }
+
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_tumbl-three-viewer"))
+        .arg("--path")
+        .arg(&base_dir)
+        .args(["stats", BLOG_NAME])
+        .env("RUST_LOG", "error")
+        .output()?;
+    let elapsed = start.elapsed();
+    if !output.status.success() {
+        panic!(
+            "Loading failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    println!("Loaded {} posts in {:.2?}", posts, elapsed);
+
+    // The prefixes searched for the posts' `downloaded_media_files`
+    let prefixes = (0..posts)
+        .map(|id| format!("tumblr_{:x}_1280.", id * 7919))
+        .collect::<Vec<_>>();
+    let blog_dir = BlogDir::new(&blog_dir);
+
+    let start = Instant::now();
+    let found = prefixes
+        .iter()
+        .filter(|prefix| !blog_dir.find_files_starting_with(prefix).is_empty())
+        .count();
+    println!(
+        "Found {} files with the index in {:.2?}",
+        found,
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let found = prefixes
+        .iter()
+        .filter(|prefix| !linear_scan(&blog_dir, prefix).is_empty())
+        .count();
+    println!(
+        "Found {} files with a linear scan in {:.2?}",
+        found,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// How files were found before the index, checking every file for each search
+fn linear_scan<'a>(blog_dir: &'a BlogDir, starting_with: &str) -> Vec<&'a String> {
+    blog_dir
+        .files
+        .iter()
+        .filter(|f| f.starts_with(starting_with))
+        .collect()
+}
+
+/// A TumblThree blog in the JSON format, the media files are empty
+fn create_blog(blog_dir: &Path, posts: usize) -> std::io::Result<()> {
+    fs::create_dir_all(blog_dir)?;
+    let mut metadata = BufWriter::new(File::create(blog_dir.join("images.txt"))?);
+    write!(metadata, "[")?;
+    for id in 0..posts {
+        let file_name = format!("tumblr_{:x}_1280.jpg", id * 7919);
+        File::create(blog_dir.join(&file_name))?;
+        if id > 0 {
+            write!(metadata, ",")?;
+        }
+        write!(
+            metadata,
+            r#"{{"type":"photo","id":"{id}","post_url":"https://large.tumblr.com/post/{id}","date":"2020-05-04 18:00:34 GMT","tags":[],"downloaded_media_files":["{file_name}"]}}"#
+        )?;
+    }
+    write!(metadata, "]")?;
+    metadata.flush()
+}
@@ -9,7 +9,11 @@ $( document ).ready(function() {
     const TOTAL = $("#total")
     const SHOWING = $("#showing")
     const SORT = $("#sort")
+    const SEED = $("#seed")
+    const DOWNLOAD = $("#download")
+    const BLOG_INFO = $("#blog-info")
 
+    let BLOGS = {};
     let ALL_POSTS = [];
     let FILTERED_POSTS = [];
 
@@ -28,7 +32,11 @@ $( document ).ready(function() {
             placeholder.setAttribute('disabled', true);
             placeholder.setAttribute('selected', true);
             BLOG_CHOICE.append(placeholder);
-            list.forEach((d) => BLOG_CHOICE.append(new Option(d,d)));
+            list.forEach((blog) => {
+                BLOGS[blog.name] = blog;
+                const label = blog.title ? `${blog.name} (${blog.title})` : blog.name;
+                BLOG_CHOICE.append(new Option(label, blog.name));
+            });
             BLOG_CHOICE.attr('disabled' , false);
         }
     ).catch((e) => {
@@ -37,16 +45,31 @@ $( document ).ready(function() {
 
     BLOG_CHOICE.change(function() {
         const blog = $(this).val();
-        $.get( BASE_URL + "/blogs/" + blog ).then((posts) => {
+        BLOG_INFO.text(blog_info(BLOGS[blog]));
+        load_posts();
+    });
+
+    // Loads the posts of the selected blog, in the order given by the seed when shuffling
+    function load_posts() {
+        const blog = BLOG_CHOICE.val();
+        if (!blog) {
+            return;
+        }
+        const params = {};
+        if (SORT[0].value === "Shuffle") {
+            params["seed"] = SEED[0].value;
+        }
+        $.get( BASE_URL + "/blogs/" + blog + "?" + $.param(params) ).then((posts) => {
             ALL_POSTS = posts.map(Post.deserialize);
             TOTAL.text(`Total: ${ALL_POSTS.length}`);
             apply_filters();
             update_page_choice();
             render_posts();
+            DOWNLOAD.attr('disabled', false);
         }).catch((e) => {
             alert(e.responseText);
         })
-    });
+    }
 
     PAGE_CHOICE.change(function() {
         apply_filters();
@@ -55,7 +78,37 @@ $( document ).ready(function() {
 
     TYPE.change(function() { refresh() });
 
-    SORT.change(function() { refresh() });
+    SORT.change(function() {
+        const shuffle = SORT[0].value === "Shuffle";
+        SEED.attr('hidden', !shuffle);
+        if (shuffle) {
+            if (SEED[0].value.length === 0) {
+                SEED[0].value = Math.floor(Math.random() * 1000000000).toString();
+            }
+            load_posts();
+        } else {
+            refresh();
+        }
+    });
+
+    // Sharing the seed shows the same shuffled order
+    SEED.change(function() {
+        if (/^\d+$/.test(SEED[0].value)) {
+            load_posts();
+        }
+    });
+
+    // Downloads the currently filtered posts
+    DOWNLOAD.click(function() {
+        const params = {};
+        if (TYPE[0].value !== "All") {
+            params["type"] = TYPE[0].value;
+        }
+        if (SEARCH[0].value.length > 0) {
+            params["search"] = SEARCH[0].value;
+        }
+        window.location = BASE_URL + "/blogs/" + BLOG_CHOICE.val() + "/zip?" + $.param(params);
+    });
 
     SEARCH.on("input", function(e) {
         clearTimeout(this.thread);
@@ -81,6 +134,9 @@ $( document ).ready(function() {
         FILTERED_POSTS = FILTERED_POSTS.filter((p) => {
             return search.length === 0 || p.matches_search(search)
         })
+        if (sort === "Shuffle") {
+            return;
+        }
         FILTERED_POSTS.sort(function (a, b) {
             if (sort === "Oldest") {
                 return a.id - b.id;
@@ -167,18 +223,18 @@ class Post {
 }
 
 class Image extends Post {
-    photo_urls;
+    photos;
     caption;
 
     constructor(json) {
         super(json);
-        this.photo_urls = json["photo_urls"]
+        this.photos = json["photos"]
         this.caption = json["caption"]
     }
 
     render() {
         const header = super.render_header();
-        const images = this.photo_urls.map(render_image).join("\n")
+        const images = this.photos.map(render_media).join("\n")
         const footer = super.render_footer();
         return [header, this.caption, images, footer].join("\n")
     }
@@ -193,12 +249,12 @@ class Image extends Post {
 }
 
 class Video extends Post {
-    url;
+    video;
     caption;
 
     constructor(json) {
         super(json)
-        this.url = json["url"]
+        this.video = json["video"]
         this.caption = json["caption"]
     }
 
@@ -206,7 +262,7 @@ class Video extends Post {
         const header = super.render_header();
         const footer = super.render_footer();
         const caption = `<div>${this.caption}</div>`
-        const video = render_video(this.url);
+        const video = this.video ? render_media(this.video) : "";
         return [header, caption, video, footer].join("\n")
     }
 
@@ -222,20 +278,21 @@ class Video extends Post {
 class Text extends Post {
     title;
     body;
-    media_urls;
+    media;
 
     constructor(json) {
         super(json);
         this.title = json["title"]
         this.body = json["body"]
-        this.media_urls = json["media_urls"]
+        this.media = json["media"]
     }
 
     render() {
         const header = super.render_header();
         const footer = super.render_footer();
         const title = this.title ? `<h4>${this.title}</h4>` : "";
-        const media = this.media_urls.map(render_text_media).join("\n")
+        // Images within the body are already shown there
+        const media = this.media.filter(media => !media.inline).map(render_media).join("\n")
         return [header, title, this.body, media, footer].join("\n")
     }
 
@@ -245,9 +302,9 @@ class Text extends Post {
 
     types() {
         const out = [PostType.Text];
-        if (this.media_urls.some(url => get_url_type(url) === TextMediaType.Video)) {
+        if (this.media.some(media => media.kind === MediaKind.Video)) {
             out.push(PostType.Video)
-        } else if (this.media_urls.some(url => get_url_type(url) === TextMediaType.Image)) {
+        } else if (this.media.some(media => media.kind === MediaKind.Image)) {
             out.push(PostType.Image)
         }
         return out;
@@ -256,16 +313,20 @@ class Text extends Post {
 
 class Answer extends Post {
     body;
+    media;
 
     constructor(json) {
         super(json);
         this.body = json["body"]
+        this.media = json["media"]
     }
 
     render() {
         const header = super.render_header();
         const footer = super.render_footer();
-        return [header, this.body, footer].join("\n")
+        // Images within the body are already shown there
+        const media = this.media.filter(media => !media.inline).map(render_media).join("\n")
+        return [header, this.body, media, footer].join("\n")
     }
 
     matches_search(search) {
@@ -278,34 +339,104 @@ class Answer extends Post {
 }
 
 
-function render_image(url) {
-    return `<img src="${url}" alt="[image]">`;
+// Summary of the details TumblThree recorded for a blog
+function blog_info(blog) {
+    const parts = [];
+    if (blog.url) {
+        parts.push(blog.url);
+    }
+    if (blog.last_download) {
+        parts.push(`Last downloaded: ${blog.last_download}`);
+    }
+    if (blog.posts_online != null) {
+        parts.push(`Posts online: ${blog.posts_online}`);
+    }
+    if (blog.items_found != null) {
+        parts.push(`Downloaded: ${blog.items_downloaded} of ${blog.items_found}`);
+    }
+    return parts.join(" | ");
 }
 
-function render_video(url) {
-    return `<video controls><source src="${url}"></video>`;
+// Images with thumbnails are loaded at the size they are shown at, linking to the original
+// Space is reserved for images with known dimensions, so the page doesn't shift as they load
+function render_image(url, alt, thumbnails = [], width = null, height = null) {
+    const text = alt ? alt.replaceAll("&", "&amp;").replaceAll('"', "&quot;") : "[image]";
+    const size = width && height ? ` width="${width}" height="${height}"` : "";
+    if (thumbnails.length === 0) {
+        return `<img src="${url}"${size} alt="${text}">`;
+    }
+    const srcset = thumbnails.map((thumbnail) => `${thumbnail.url} ${thumbnail.width}w`).join(", ");
+    const src = thumbnails[thumbnails.length - 1].url;
+    return `<a href="${url}" target="_blank"><img src="${src}" srcset="${srcset}" sizes="540px" loading="lazy"${size} alt="${text}"></a>`;
 }
 
-function render_text_media(url) {
-    const type = get_url_type(url);
-    if (type === TextMediaType.Video) {
-        return render_video(url);
-    } else if (type === TextMediaType.Image) {
-        return render_image(url);
+// Links to the other files found for an image, such as smaller sizes
+function render_alternatives(urls) {
+    if (urls.length === 0) {
+        return "";
     }
+    const links = urls.map((url) => `<a href="${url}" target="_blank">${url.split("/").pop()}</a>`);
+    return `<div class="alternatives">Other copies: ${links.join(", ")}</div>`;
+}
+
+function render_video(url, mime_type, poster = null) {
+    const type = mime_type ? ` type="${mime_type}"` : "";
+    const poster_attr = poster ? ` poster="${poster}"` : "";
+    return `<video controls preload="metadata"${poster_attr}><source src="${url}"${type}></video>`;
+}
+
+// The length of a video as minutes and seconds, e.g. 2:05
+function format_duration(seconds) {
+    const rounded = Math.round(seconds);
+    return `${Math.floor(rounded / 60)}:${String(rounded % 60).padStart(2, "0")}`;
+}
+
+function render_audio(url, mime_type) {
+    const type = mime_type ? ` type="${mime_type}"` : "";
+    return `<audio controls><source src="${url}"${type}></audio>`;
 }
 
-function get_url_type(url) {
-    if (url.endsWith(".mp4")) {
-        return TextMediaType.Video
+// A media file of a post, by the kind detected from its contents, or a placeholder linking to
+// where it was downloaded from if it is missing
+function render_media(media) {
+    if (media.status === MediaStatus.Missing) {
+        return render_missing(media);
+    }
+    let rendered;
+    if (media.kind === MediaKind.Video) {
+        rendered = render_video(media.url, media.mime_type, media.poster);
+        if (media.duration != null) {
+            rendered += `<div class="duration">${format_duration(media.duration)}</div>`;
+        }
+    } else if (media.kind === MediaKind.Audio) {
+        rendered = render_audio(media.url, media.mime_type);
     } else {
-        return TextMediaType.Image
+        rendered = render_image(media.url, media.alt_text, media.thumbnails ?? [], media.width, media.height);
     }
+    return rendered + render_alternatives(media.alternatives ?? []);
 }
 
-const TextMediaType = {
-    Video: 'Video',
-    Image: 'Image',
+function render_missing(media) {
+    const escape = (text) => text.replaceAll("&", "&amp;").replaceAll("<", "&lt;").replaceAll('"', "&quot;");
+    const original = media.original_url
+        ? (media.original_url.startsWith("http")
+            ? `<a href="${escape(media.original_url)}" target="_blank">${escape(media.original_url)}</a>`
+            : escape(media.original_url))
+        : "";
+    return `<div class="missing-media" title="${escape(media.reason ?? "")}">Missing ${media.kind} ${original}</div>`;
+}
+
+const MediaKind = {
+    Image: 'image',
+    Video: 'video',
+    Audio: 'audio',
+    Other: 'other',
+};
+
+const MediaStatus = {
+    Found: 'found',
+    Substituted: 'substituted',
+    Missing: 'missing',
 };
 
 const PostType = {
@@ -0,0 +1,336 @@
+//! Parses posts returned by the Tumblr API v2, in either the legacy format or the Neue Post
+//! Format, and reads directories of saved API responses
+
+use crate::blog::Blog;
+use crate::blog_dir::BlogDir;
+use crate::model::{Answer, Image, Media, MediaKind, Post, PostCommon, PostType, Text, Video};
+use crate::npf::NpfContent;
+use crate::text_parser::{body_images, image_search_prefix, image_urls};
+use crate::utils::escape_html;
+use anyhow::Context;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct ApiPost {
+    id: u64,
+    post_url: Option<String>,
+    date: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(flatten)]
+    r#type: ApiPostType,
+}
+
+/// The post formats of the Tumblr API v2, posts requested with `npf=true` have the type
+/// `blocks` regardless of their legacy type
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ApiPostType {
+    Text {
+        title: Option<String>,
+        #[serde(default)]
+        body: String,
+    },
+    Photo {
+        #[serde(default)]
+        photos: Vec<ApiPhoto>,
+        caption: Option<String>,
+    },
+    Quote {
+        #[serde(default)]
+        text: String,
+        source: Option<String>,
+    },
+    Link {
+        title: Option<String>,
+        url: String,
+        description: Option<String>,
+    },
+    Chat {
+        title: Option<String>,
+        #[serde(default)]
+        body: String,
+    },
+    Audio {
+        caption: Option<String>,
+        audio_url: Option<String>,
+        track_name: Option<String>,
+        artist: Option<String>,
+    },
+    Video {
+        caption: Option<String>,
+        video_url: Option<String>,
+    },
+    Answer {
+        #[serde(default)]
+        question: String,
+        #[serde(default)]
+        answer: String,
+    },
+    Blocks(NpfContent),
+}
+
+#[derive(Deserialize)]
+struct ApiPhoto {
+    original_size: ApiPhotoSize,
+}
+
+#[derive(Deserialize)]
+struct ApiPhotoSize {
+    url: String,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Parse a single post from the API, with its media resolved against the files in `media`
+pub fn parse_post(json: Value, media: &BlogDir) -> anyhow::Result<Post> {
+    serde_json::from_value::<ApiPost>(json)?.into_post(media)
+}
+
+/// Whether a directory contains saved API responses: a top level JSON file holding a response,
+/// its list of posts or a single post, rather than other JSON such as a project's `package.json`
+/// Only the first JSON file that parses is checked, as every directory is checked for each kind
+pub fn is_dump_dir(path: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(path) else {
+        return false;
+    };
+    let json = entries
+        .flatten()
+        .filter(|entry| {
+            entry.file_type().is_ok_and(|t| t.is_file())
+                && entry.path().extension().is_some_and(|ext| ext == "json")
+        })
+        .find_map(|entry| {
+            let text = fs::read_to_string(entry.path()).ok()?;
+            serde_json::from_str::<Value>(&text).ok()
+        });
+    json.is_some_and(|json| {
+        posts_in(json)
+            .first()
+            .is_some_and(|post| post.get("id").is_some() && post.get("type").is_some())
+    })
+}
+
+/// Loads all posts from the saved API responses, sorted by id, posts that can't be read are
+/// skipped unless `strict`
+/// Each file may contain a whole response, the list of posts or a single post, posts saved
+/// more than once, such as from overlapping pages, are only included once
+pub fn load_dump(blog: &Blog, strict: bool) -> anyhow::Result<Vec<Post>> {
+    let media = BlogDir::new(&blog.path);
+    let mut posts = BTreeMap::new();
+    // Only the top level, subdirectories may hold media files
+    let responses = media
+        .files
+        .iter()
+        .filter(|file| !file.contains('/') && file.ends_with(".json"));
+    for file in responses {
+        let text = fs::read_to_string(media.path.join(file))?;
+        let json = serde_json::from_str::<Value>(&text).context(file.clone())?;
+        for post in posts_in(json) {
+            let id = post.get("id").cloned().unwrap_or_default();
+            match parse_post(post, &media) {
+                Ok(post) => {
+                    posts.insert(post.common.id, post);
+                }
+                Err(e) if strict => return Err(e.context(format!("Post {} in {}", id, file))),
+                Err(e) => log::warn!("Skipping post {} in {}: {:#}", id, file, e),
+            }
+        }
+    }
+    Ok(posts.into_values().collect())
+}
+
+/// The posts within a saved response
+fn posts_in(json: Value) -> Vec<Value> {
+    match json {
+        Value::Array(posts) => posts,
+        Value::Object(mut map) => {
+            if let Some(response) = map.remove("response") {
+                posts_in(response)
+            } else if let Some(posts @ Value::Array(_)) = map.remove("posts") {
+                posts_in(posts)
+            } else if map.contains_key("id") && map.contains_key("type") {
+                vec![Value::Object(map)]
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+impl ApiPost {
+    fn into_post(self, media: &BlogDir) -> anyhow::Result<Post> {
+        let id = self.id;
+        let text = |title: Option<String>, body: String| {
+            let (body, media) = body_images(&body, media);
+            PostType::Text(Text { title, body, media })
+        };
+        let r#type = match self.r#type {
+            ApiPostType::Text { title, body } => text(title, body),
+            ApiPostType::Photo { photos, caption } => PostType::Image(Image {
+                photos: photos
+                    .iter()
+                    .map(|photo| {
+                        let size = &photo.original_size;
+                        Media {
+                            width: size.width,
+                            height: size.height,
+                            ..Media::from_matches(
+                                MediaKind::Image,
+                                Some(&size.url),
+                                image_search_prefix(&size.url).ok(),
+                                image_urls(&size.url, media),
+                            )
+                        }
+                    })
+                    .collect(),
+                caption,
+            }),
+            ApiPostType::Quote {
+                text: quote,
+                source,
+            } => {
+                let body = format!("<blockquote>{}</blockquote>", quote);
+                text(None, body + &source.unwrap_or_default())
+            }
+            ApiPostType::Link {
+                title,
+                url,
+                description,
+            } => {
+                let link = format!(
+                    "<p><a href=\"{}\">{}</a></p>",
+                    escape_html(&url),
+                    escape_html(title.as_deref().unwrap_or(&url))
+                );
+                text(None, link + &description.unwrap_or_default())
+            }
+            ApiPostType::Chat { title, body } => {
+                let lines = body.lines().map(escape_html).collect::<Vec<_>>();
+                text(title, format!("<p>{}</p>", lines.join("<br>")))
+            }
+            ApiPostType::Audio {
+                caption,
+                audio_url,
+                track_name,
+                artist,
+            } => {
+                let title = match (artist, track_name) {
+                    (Some(artist), Some(track)) => Some(format!("{} - {}", artist, track)),
+                    (artist, track) => track.or(artist),
+                };
+                let audio = audio_url.map(|url| {
+                    let found = resolve_post_media(&[&url], id, media);
+                    Media::from_matches(
+                        MediaKind::Audio,
+                        Some(&url),
+                        media_search_prefix(&url).as_deref(),
+                        found.into_iter().collect(),
+                    )
+                });
+                let (body, images) = body_images(&caption.unwrap_or_default(), media);
+                PostType::Text(Text {
+                    title,
+                    body,
+                    media: audio.into_iter().chain(images).collect(),
+                })
+            }
+            ApiPostType::Video { caption, video_url } => PostType::Video(Video {
+                video: video_url.map(|url| {
+                    let found = resolve_post_media(&[&url], id, media);
+                    let urls = found.into_iter().collect();
+                    Box::new(Media::from_matches(
+                        MediaKind::Video,
+                        Some(&url),
+                        media_search_prefix(&url).as_deref(),
+                        urls,
+                    ))
+                }),
+                caption,
+            }),
+            ApiPostType::Answer { question, answer } => {
+                let (answer, media) = body_images(&answer, media);
+                PostType::Answer(Answer {
+                    body: Some(format!("<em>{}</em><br>{}", question, answer)),
+                    media,
+                })
+            }
+            ApiPostType::Blocks(content) => content.into_post_type(id, media),
+        };
+        Ok(Post {
+            common: PostCommon {
+                id,
+                post_url: self.post_url,
+                date: self.date,
+                tags: self.tags,
+            },
+            r#type,
+        })
+    }
+}
+
+/// Find the file on disk for a media URL saved with its original name, alternative URLs for
+/// the same media, such as other sizes, are tried in order
+pub fn find_media_file(urls: &[&str], media: &BlogDir) -> Option<String> {
+    find_media_files(urls, media).into_iter().next()
+}
+
+/// The file name prefix searched for a media URL saved with its original name, its name without
+/// the extension
+pub fn media_search_prefix(url: &str) -> Option<String> {
+    let filename = url.rsplit('/').next().unwrap_or_default();
+    let filename = filename.split('?').next().unwrap_or_default();
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    (!stem.is_empty()).then(|| format!("{}.", stem))
+}
+
+/// Like [find_media_file], but returns the URLs of every matching file, best first
+pub fn find_media_files(urls: &[&str], media: &BlogDir) -> Vec<String> {
+    let filenames = urls
+        .iter()
+        .map(|url| {
+            let filename = url.rsplit('/').next().unwrap_or_default();
+            filename.split('?').next().unwrap_or_default()
+        })
+        .filter(|filename| !filename.is_empty())
+        .collect::<Vec<_>>();
+    let exact = filenames
+        .iter()
+        .filter_map(|filename| media.find_file(filename));
+    let by_stem = filenames.iter().flat_map(|filename| {
+        let stem = filename
+            .rsplit_once('.')
+            .map_or(*filename, |(stem, _)| stem);
+        media.find_files_starting_with(&format!("{}.", stem))
+    });
+    exact
+        .chain(by_stem)
+        .unique()
+        .map(|matched| media.file_url(&matched))
+        .collect()
+}
+
+/// Find the file on disk for a video or audio URL, which is saved either with its original
+/// name or named after the post, logging when there is no such file
+pub fn resolve_post_media(urls: &[&str], post_id: u64, media: &BlogDir) -> Option<String> {
+    let found = find_media_file(urls, media).or_else(|| {
+        [format!("{}.", post_id), format!("{}_", post_id)]
+            .iter()
+            .find_map(|prefix| media.find_file_starting_with(prefix))
+            .map(|matched| media.file_url(&matched))
+    });
+    if found.is_none() {
+        log::warn!(
+            "Unable to find file matching {} for post {}",
+            urls.first().unwrap_or(&""),
+            post_id
+        );
+    }
+    found
+}
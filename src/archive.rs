@@ -0,0 +1,212 @@
+//! Reads blogs stored as `.zip` or `.tar` archives without extracting them
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+use zip::{CompressionMethod, ZipArchive};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let ext = path.extension()?;
+        if ext.eq_ignore_ascii_case("zip") {
+            Some(Format::Zip)
+        } else if ext.eq_ignore_ascii_case("tar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// A file within an archive
+struct Entry {
+    /// The full name within the archive
+    name: String,
+    size: u64,
+    /// Offset of the file data, if it is stored uncompressed
+    data_offset: Option<u64>,
+    /// Index within the archive, ZIP entries are read by their index in the central directory
+    index: usize,
+}
+
+/// The index of the files within an archive
+pub struct Archive {
+    path: PathBuf,
+    format: Format,
+    /// Files by their path within the blog
+    entries: BTreeMap<String, Entry>,
+    /// The opened ZIP, so that its central directory isn't read again for each compressed file
+    zip: Option<Mutex<ZipArchive<File>>>,
+}
+
+/// Archive indexes along with the modification time of the archive when they were read
+type IndexCache = HashMap<PathBuf, (SystemTime, Arc<Archive>)>;
+
+/// Whether a file is an archive that could contain a blog
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && Format::of(path).is_some()
+}
+
+/// Opens an archive, indexes are kept until the archive is modified as reading the index of
+/// a large tar requires seeking through the whole file
+pub fn open(path: &Path) -> anyhow::Result<Arc<Archive>> {
+    static ARCHIVES: Lazy<Mutex<IndexCache>> = Lazy::new(Default::default);
+
+    let modified = path.metadata()?.modified()?;
+    if let Some((cached_modified, archive)) = ARCHIVES.lock().unwrap().get(path) {
+        if *cached_modified == modified {
+            return Ok(archive.clone());
+        }
+    }
+    let archive = Arc::new(Archive::index(path).with_context(|| path.display().to_string())?);
+    ARCHIVES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), (modified, archive.clone()));
+    Ok(archive)
+}
+
+impl Archive {
+    fn index(path: &Path) -> anyhow::Result<Self> {
+        let format = Format::of(path).context("Not a supported archive")?;
+        let mut entries = Vec::new();
+        let mut opened_zip = None;
+        match format {
+            Format::Zip => {
+                let mut zip = ZipArchive::new(File::open(path)?)?;
+                for index in 0..zip.len() {
+                    let file = zip.by_index_raw(index)?;
+                    if file.is_file() {
+                        let data_offset = (file.compression() == CompressionMethod::Stored)
+                            .then(|| file.data_start());
+                        entries.push(Entry {
+                            name: file.name().to_string(),
+                            size: file.size(),
+                            data_offset,
+                            index,
+                        });
+                    }
+                }
+                opened_zip = Some(Mutex::new(zip));
+            }
+            Format::Tar => {
+                let mut tar = tar::Archive::new(File::open(path)?);
+                for (index, entry) in tar.entries_with_seek()?.enumerate() {
+                    let entry = entry?;
+                    if entry.header().entry_type().is_file() {
+                        entries.push(Entry {
+                            name: entry.path()?.to_string_lossy().into_owned(),
+                            size: entry.size(),
+                            data_offset: Some(entry.raw_file_position()),
+                            index,
+                        });
+                    }
+                }
+            }
+        }
+
+        // A blog directory is usually archived along with the directory itself
+        let prefix = entries
+            .iter()
+            .map(|entry| entry.name.split_once('/').map(|(dir, _)| dir))
+            .reduce(|a, b| a.filter(|a| Some(*a) == b))
+            .flatten()
+            .map(|dir| format!("{}/", dir))
+            .unwrap_or_default();
+        let entries = entries
+            .into_iter()
+            .map(|entry| (entry.name[prefix.len()..].to_string(), entry))
+            .collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            entries,
+            zip: opened_zip,
+        })
+    }
+
+    /// The opened ZIP, compressed files are read one at a time as they share the file handle
+    fn zip(&self) -> anyhow::Result<MutexGuard<'_, ZipArchive<File>>> {
+        let zip = self.zip.as_ref().context("Not a ZIP archive")?;
+        Ok(zip.lock().unwrap())
+    }
+
+    /// Paths of all files within the blog
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn contains(&self, file: &str) -> bool {
+        self.entries.contains_key(file)
+    }
+
+    /// The uncompressed size of a file, if there is such a file
+    pub fn file_size(&self, file: &str) -> Option<u64> {
+        self.entries.get(file).map(|entry| entry.size)
+    }
+
+    /// Read up to `len` bytes from an offset within a file, returns `None` if there is no such
+    /// file
+    /// Compressed files are only decompressed up to the end of the range
+    pub fn read_range(
+        &self,
+        file: &str,
+        start: u64,
+        len: usize,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entries.get(file) else {
+            return Ok(None);
+        };
+        let mut data = Vec::with_capacity(len);
+        match (entry.data_offset, self.format) {
+            (Some(offset), _) => {
+                let mut file = File::open(&self.path)?;
+                file.seek(SeekFrom::Start(offset + start.min(entry.size)))?;
+                file.take(entry.size.saturating_sub(start).min(len as u64))
+                    .read_to_end(&mut data)?;
+            }
+            (None, Format::Zip) => {
+                let mut zip = self.zip()?;
+                let mut reader = zip.by_index(entry.index)?;
+                io::copy(&mut (&mut reader).take(start), &mut io::sink())?;
+                reader.take(len as u64).read_to_end(&mut data)?;
+            }
+            (None, Format::Tar) => unreachable!("Tar entries are always uncompressed"),
+        }
+        Ok(Some(data))
+    }
+
+    /// Open a file for reading along with its size, returns `None` if there is no such file
+    /// Uncompressed files are read directly from the archive, others are decompressed into
+    /// memory
+    pub fn open_file(&self, file: &str) -> anyhow::Result<Option<(Box<dyn Read + Send>, u64)>> {
+        let Some(entry) = self.entries.get(file) else {
+            return Ok(None);
+        };
+        let reader: Box<dyn Read + Send> = match (entry.data_offset, self.format) {
+            (Some(offset), _) => {
+                let mut file = File::open(&self.path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Box::new(file.take(entry.size))
+            }
+            (None, Format::Zip) => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                self.zip()?.by_index(entry.index)?.read_to_end(&mut data)?;
+                Box::new(Cursor::new(data))
+            }
+            (None, Format::Tar) => unreachable!("Tar entries are always uncompressed"),
+        };
+        Ok(Some((reader, entry.size)))
+    }
+}
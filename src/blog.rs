@@ -0,0 +1,338 @@
+use crate::blog_dir::{create_file_url, BlogDir};
+use crate::model::Post;
+use crate::settings::{self, BlogSettings, INDEX_DIR};
+use crate::stats::Completeness;
+use crate::{api_v2, archive, gallery_dl, load_blog, tumblr_export, tumblr_utils, MetadataType};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The backup formats that a blog can be read from
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlogKind {
+    /// A TumblThree download directory, or an archive of one
+    TumblThree,
+    /// An official Tumblr blog export, either extracted or archived
+    TumblrExport,
+    /// A tumblr-utils backup directory
+    TumblrUtils,
+    /// A gallery-dl download directory with metadata sidecar files
+    GalleryDl,
+    /// A directory of saved Tumblr API v2 responses
+    ApiDump,
+}
+
+/// The detected kinds of backups by their path, along with the modification time of the path
+/// when it was detected
+type KindCache = HashMap<PathBuf, (SystemTime, Option<BlogKind>)>;
+
+impl BlogKind {
+    /// Detect the kind of backup at a path within the base directory
+    /// Detecting may read the files within it, so the kind is kept until the path is modified,
+    /// as it's detected for every request
+    pub fn detect(path: &Path) -> Option<Self> {
+        static KINDS: Lazy<Mutex<KindCache>> = Lazy::new(Default::default);
+
+        let Ok(modified) = path.metadata().and_then(|m| m.modified()) else {
+            return None;
+        };
+        if let Some((cached_modified, kind)) = KINDS.lock().unwrap().get(path) {
+            if *cached_modified == modified {
+                return *kind;
+            }
+        }
+        let kind = Self::detect_uncached(path);
+        KINDS
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (modified, kind));
+        kind
+    }
+
+    fn detect_uncached(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            // TumblThree keeps its settings in the Index directory
+            if path.file_name().is_some_and(|name| name == INDEX_DIR) {
+                return None;
+            }
+            if enum_iterator::all::<MetadataType>().any(|t| path.join(t.file_name()).exists()) {
+                return Some(BlogKind::TumblThree);
+            }
+            if tumblr_export::is_export_dir(path) {
+                return Some(BlogKind::TumblrExport);
+            }
+            if tumblr_utils::is_backup_dir(path) {
+                return Some(BlogKind::TumblrUtils);
+            }
+            if gallery_dl::is_gallery_dir(path) {
+                return Some(BlogKind::GalleryDl);
+            }
+            if api_v2::is_dump_dir(path) {
+                return Some(BlogKind::ApiDump);
+            }
+        } else if archive::is_archive(path) {
+            let archive = archive::open(path)
+                .inspect_err(|e| log::warn!("Unable to read archive: {:#}", e))
+                .ok()?;
+            if enum_iterator::all::<MetadataType>().any(|t| archive.contains(t.file_name())) {
+                return Some(BlogKind::TumblThree);
+            }
+            if tumblr_export::is_export_archive(&archive) {
+                return Some(BlogKind::TumblrExport);
+            }
+        }
+        None
+    }
+}
+
+/// A blog within the base directory
+pub struct Blog {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: BlogKind,
+    /// The URL the server provides the blog's media files at
+    pub media_url: String,
+    /// The URL the server provides thumbnails of the blog's images at
+    pub thumbnail_url: String,
+    /// The URL the server provides the cover art embedded in the blog's videos at
+    pub poster_url: String,
+}
+
+impl Blog {
+    /// Loads all posts, sorted by id, posts that can't be read are skipped
+    /// The media files aren't described, as that requires reading them, see
+    /// [crate::sniff::describe_media]
+    pub fn load(&self) -> anyhow::Result<Vec<Post>> {
+        self.load_posts(false)
+    }
+
+    /// Loads all posts, sorted by id, failing if any post can't be read rather than skipping it,
+    /// for when every file referenced by the posts must be known
+    pub fn load_strict(&self) -> anyhow::Result<Vec<Post>> {
+        self.load_posts(true)
+    }
+
+    fn load_posts(&self, strict: bool) -> anyhow::Result<Vec<Post>> {
+        match self.kind {
+            BlogKind::TumblThree => load_blog(self),
+            BlogKind::TumblrExport => tumblr_export::load(self),
+            BlogKind::TumblrUtils => tumblr_utils::load(self, strict),
+            BlogKind::GalleryDl => gallery_dl::load(self, strict),
+            BlogKind::ApiDump => api_v2::load_dump(self, strict),
+        }
+    }
+
+    pub fn is_archive(&self) -> bool {
+        !self.path.is_dir()
+    }
+
+    /// The files within the blog
+    pub fn blog_dir(&self) -> anyhow::Result<BlogDir> {
+        if self.is_archive() {
+            Ok(BlogDir::from_files(
+                self.path.clone(),
+                archive::open(&self.path)?
+                    .files()
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                Some(self.media_url.clone()),
+            ))
+        } else {
+            Ok(BlogDir::new(&self.path))
+        }
+    }
+
+    /// URL for a file within the blog, files on disk are referenced directly, whereas files
+    /// within an archive must be requested from the server
+    pub fn file_url(&self, file: &str) -> String {
+        if self.is_archive() {
+            format!("{}/{}", self.media_url, file)
+        } else {
+            create_file_url(&self.path, file)
+        }
+    }
+
+    /// Read a whole file within the blog, returns `None` if there is no such file
+    pub fn read_file(&self, file: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some((mut reader, size)) = self.open_file(file)? else {
+            return Ok(None);
+        };
+        let mut data = Vec::with_capacity(size as usize);
+        reader.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Read up to `len` bytes from an offset within a file within the blog, returns `None` if
+    /// there is no such file
+    pub fn read_range(
+        &self,
+        file: &str,
+        start: u64,
+        len: usize,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(relative) = relative_path(file) else {
+            return Ok(None);
+        };
+        if self.is_archive() {
+            return archive::open(&self.path)?.read_range(file, start, len);
+        }
+        let mut file = match File::open(self.path.join(relative)) {
+            Ok(file) if file.metadata()?.is_file() => file,
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(start))?;
+        let mut data = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// The size of a file within the blog, without reading it, returns `None` if there is no
+    /// such file
+    pub fn file_size(&self, file: &str) -> anyhow::Result<Option<u64>> {
+        let Some(relative) = relative_path(file) else {
+            return Ok(None);
+        };
+        if self.is_archive() {
+            return Ok(archive::open(&self.path)?.file_size(file));
+        }
+        match fs::metadata(self.path.join(relative)) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.len())),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// When a file within the blog was last modified, files within an archive are as recent as
+    /// the archive, returns `None` if there is no such file
+    pub fn modified(&self, file: &str) -> anyhow::Result<Option<SystemTime>> {
+        let Some(relative) = relative_path(file) else {
+            return Ok(None);
+        };
+        if self.is_archive() {
+            if archive::open(&self.path)?.file_size(file).is_none() {
+                return Ok(None);
+            }
+            return Ok(Some(fs::metadata(&self.path)?.modified()?));
+        }
+        match fs::metadata(self.path.join(relative)) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.modified()?)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Open a file within the blog for reading along with its size, returns `None` if there
+    /// is no such file
+    pub fn open_file(&self, file: &str) -> anyhow::Result<Option<(Box<dyn Read + Send>, u64)>> {
+        let Some(relative) = relative_path(file) else {
+            return Ok(None);
+        };
+        if self.is_archive() {
+            return archive::open(&self.path)?.open_file(file);
+        }
+        match File::open(self.path.join(relative)) {
+            Ok(file) if file.metadata()?.is_file() => {
+                let size = file.metadata()?.len();
+                Ok(Some((Box::new(file), size)))
+            }
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A blog within the base directory, with the details TumblThree recorded when it was last
+/// downloaded
+#[derive(Serialize, Debug)]
+pub struct BlogSummary {
+    pub name: String,
+    pub kind: BlogKind,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// When TumblThree last completed downloading the blog
+    pub last_download: Option<String>,
+    /// Number of posts on the blog as of the last download
+    pub posts_online: Option<u64>,
+    /// Number of items TumblThree found to download
+    pub items_found: Option<u64>,
+    /// Number of items TumblThree downloaded
+    pub items_downloaded: Option<u64>,
+    /// Only included when requested, as the blog must be loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completeness: Option<Completeness>,
+    #[serde(skip)]
+    pub settings: Option<BlogSettings>,
+}
+
+/// Names of all blogs within the base directory
+pub fn find_blogs(path: &Path) -> io::Result<Vec<String>> {
+    Ok(find_blog_kinds(path)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+fn find_blog_kinds(path: &Path) -> io::Result<Vec<(String, BlogKind)>> {
+    Ok(fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let kind = BlogKind::detect(&entry.path())?;
+            Some((entry.file_name().to_string_lossy().into_owned(), kind))
+        })
+        .collect())
+}
+
+/// All blogs within the base directory, sorted by name
+pub fn list_blogs(path: &Path) -> io::Result<Vec<BlogSummary>> {
+    let mut index = settings::read_index(path);
+    let mut blogs = find_blog_kinds(path)?
+        .into_iter()
+        .map(|(name, kind)| {
+            // Archived blogs are named after the blog they contain
+            let settings = index.remove(&name).or_else(|| {
+                let (stem, _) = name.rsplit_once('.')?;
+                index.remove(stem)
+            });
+            let s = settings.as_ref();
+            BlogSummary {
+                name,
+                kind,
+                title: s.and_then(|s| s.title.clone()).filter(|t| !t.is_empty()),
+                description: s
+                    .and_then(|s| s.description.clone())
+                    .filter(|d| !d.is_empty()),
+                url: s.and_then(|s| s.url.clone()),
+                last_download: s
+                    .and_then(|s| s.last_complete_crawl)
+                    .map(|date| date.format("%Y-%m-%d %H:%M:%S GMT").to_string()),
+                posts_online: s.map(|s| s.posts),
+                items_found: s.map(|s| s.total_count),
+                items_downloaded: s.map(|s| s.downloaded_images),
+                completeness: None,
+                settings,
+            }
+        })
+        .collect::<Vec<_>>();
+    blogs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(blogs)
+}
+
+/// A path within a blog, rejecting absolute paths and paths leaving the blog
+fn relative_path(file: &str) -> Option<&Path> {
+    let relative = Path::new(file);
+    relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then_some(relative)
+}
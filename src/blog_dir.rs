@@ -0,0 +1,160 @@
+//! An index of the files within a blog, so that media can be found by its name or a prefix of
+//! it without scanning every file
+//! This module doesn't depend on the rest of the crate, so that the benchmarks can include it
+
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+pub fn create_file_url(blog_dir: &Path, filename: &str) -> String {
+    let path = blog_dir.join(filename).to_string_lossy().to_string();
+    let path = path.replace(r"\\?\UNC\", "//");
+    let path = path.replace(r"\\?\", "");
+    let path = path.replace('\\', "/");
+    format!("file:///{}", path)
+}
+
+pub struct BlogDir {
+    pub path: PathBuf,
+    /// The paths of the files relative to the directory, separated by `/`, including files
+    /// within subdirectories
+    pub files: BTreeSet<String>,
+    /// The paths of the files by their name, sorted so that files can be found by their name or
+    /// prefix without scanning every file, as blogs may have hundreds of thousands of files
+    by_name: BTreeMap<String, Vec<String>>,
+    /// The URL the files are served at, for directories that can't be referenced on disk
+    pub url_base: Option<String>,
+}
+
+impl BlogDir {
+    /// Index the files within a directory and its subdirectories, hidden subdirectories are
+    /// skipped
+    pub fn new(path: &Path) -> Self {
+        let mut files = BTreeSet::new();
+        let list = std::fs::read_dir(path).expect("Unable to read blog directory");
+        list_files(list, "", &mut files);
+        Self::from_files(path.to_path_buf(), files, None)
+    }
+
+    pub fn from_files(path: PathBuf, files: BTreeSet<String>, url_base: Option<String>) -> Self {
+        let mut by_name = BTreeMap::<String, Vec<String>>::new();
+        for file in &files {
+            by_name
+                .entry(file_name(file).to_string())
+                .or_default()
+                .push(file.clone());
+        }
+        Self {
+            path,
+            files,
+            by_name,
+            url_base,
+        }
+    }
+
+    pub fn file_url(&self, filename: &str) -> String {
+        match &self.url_base {
+            Some(url_base) => format!("{}/{}", url_base, filename),
+            None => create_file_url(&self.path, filename),
+        }
+    }
+
+    pub fn contains(&self, file: &str) -> bool {
+        self.files.contains(file)
+    }
+
+    /// The path of a file by its name, or its path if the name contains a `/`
+    pub fn find_file(&self, name: &str) -> Option<String> {
+        if name.contains('/') {
+            return self.contains(name).then(|| name.to_string());
+        }
+        let paths = self.by_name.get(name)?;
+        if paths.len() > 1 {
+            log::warn!("Found multiple files named {}: {:?}", name, paths);
+        }
+        paths.first().cloned()
+    }
+
+    /// The best file starting with a prefix, see [BlogDir::find_files_starting_with]
+    pub fn find_file_starting_with(&self, starting_with: &str) -> Option<String> {
+        self.find_files_starting_with(starting_with)
+            .into_iter()
+            .next()
+    }
+
+    /// The paths of all files with names starting with a prefix, or paths if the prefix contains
+    /// a `/`, best first: the largest size suffix, e.g. `_1280` over `_540`, then the largest
+    /// file, then by path
+    pub fn find_files_starting_with(&self, starting_with: &str) -> Vec<String> {
+        let range = (Bound::Included(starting_with), Bound::Unbounded);
+        let matches = if starting_with.contains('/') {
+            self.files
+                .range::<str, _>(range)
+                .take_while(|f| f.starts_with(starting_with))
+                .cloned()
+                .collect::<Vec<_>>()
+        } else {
+            self.by_name
+                .range::<str, _>(range)
+                .take_while(|(name, _)| name.starts_with(starting_with))
+                .flat_map(|(_, paths)| paths.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        if matches.len() < 2 {
+            return matches;
+        }
+        let sorted = matches
+            .into_iter()
+            .map(|file| {
+                let file_size = match &self.url_base {
+                    None => std::fs::metadata(self.path.join(&file)).map_or(0, |m| m.len()),
+                    // Files within an archive are all ranked by name
+                    Some(_) => 0,
+                };
+                (Reverse((size_suffix(&file), file_size)), file)
+            })
+            .sorted()
+            .map(|(_, file)| file)
+            .collect::<Vec<_>>();
+        log::debug!(
+            "Found multiple matches for file search {}: {:?}",
+            starting_with,
+            sorted
+        );
+        sorted
+    }
+}
+
+/// The name of a file from its path within a blog
+pub fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+/// Add the files within a directory to the index, recursing into subdirectories
+fn list_files(list: std::fs::ReadDir, prefix: &str, files: &mut BTreeSet<String>) {
+    for entry in list.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_file() {
+            files.insert(format!("{}{}", prefix, name));
+        } else if file_type.is_dir() && !name.starts_with('.') {
+            match std::fs::read_dir(entry.path()) {
+                Ok(list) => list_files(list, &format!("{}{}/", prefix, name), files),
+                Err(e) => log::warn!("Unable to read {}: {}", entry.path().display(), e),
+            }
+        }
+    }
+}
+
+/// The size from a Tumblr media file name, e.g. 1280 for `tumblr_abc_1280.jpg`
+fn size_suffix(file: &str) -> Option<u32> {
+    let file = file_name(file);
+    let stem = file.rsplit_once('.').map_or(file, |(stem, _)| stem);
+    let (_, suffix) = stem.rsplit_once('_')?;
+    let digits = suffix.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    digits.parse().ok()
+}
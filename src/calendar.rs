@@ -0,0 +1,181 @@
+//! Groups posts by their dates, like Tumblr's archive page, and finds the posts made on the same
+//! day in previous years
+
+use crate::blog::list_blogs;
+use crate::model::Post;
+use crate::{find_blog, sniff, Args};
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OnThisDayArgs {
+    /// Month and day to show posts from as `MM-DD`, defaults to today
+    #[arg(long)]
+    date: Option<MonthDay>,
+}
+
+/// Number of posts by year, month and day
+#[derive(Serialize, Debug, Default)]
+pub struct PostArchive {
+    pub posts: usize,
+    /// Number of posts without a recognised date, which are not included in the years
+    pub undated: usize,
+    pub years: BTreeMap<i32, YearArchive>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct YearArchive {
+    pub posts: usize,
+    pub months: BTreeMap<u32, MonthArchive>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct MonthArchive {
+    pub posts: usize,
+    /// Number of posts by day of the month
+    pub days: BTreeMap<u32, usize>,
+}
+
+impl PostArchive {
+    pub fn new(posts: &[Post]) -> Self {
+        let mut archive = PostArchive {
+            posts: posts.len(),
+            ..PostArchive::default()
+        };
+        for post in posts {
+            let Some(date) = post.common.parsed_date() else {
+                archive.undated += 1;
+                continue;
+            };
+            let year = archive.years.entry(date.year()).or_default();
+            year.posts += 1;
+            let month = year.months.entry(date.month()).or_default();
+            month.posts += 1;
+            *month.days.entry(date.day()).or_default() += 1;
+        }
+        archive
+    }
+}
+
+/// A day of the year, e.g. `05-17`
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct MonthDay {
+    month: u32,
+    day: u32,
+}
+
+impl MonthDay {
+    /// The current day in UTC, as post dates are in GMT
+    pub fn today() -> Self {
+        let today = Utc::now().date_naive();
+        Self {
+            month: today.month(),
+            day: today.day(),
+        }
+    }
+}
+
+impl FromStr for MonthDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Parsed within a leap year to allow the 29th of February
+        let date = NaiveDate::parse_from_str(&format!("2000-{}", s), "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date {}, expected MM-DD", s))?;
+        Ok(Self {
+            month: date.month(),
+            day: date.day(),
+        })
+    }
+}
+
+impl TryFrom<String> for MonthDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The posts from a year on a day of the year
+#[derive(Serialize, Debug)]
+pub struct YearPosts {
+    pub year: i32,
+    pub posts: Vec<BlogPost>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BlogPost {
+    pub blog: String,
+    #[serde(flatten)]
+    pub post: Post,
+}
+
+/// Posts from every blog made on the day of the year in previous years, most recent year first
+/// Blogs that can't be loaded are logged and skipped
+pub fn on_this_day(args: &Args, day: MonthDay) -> anyhow::Result<Vec<YearPosts>> {
+    let this_year = Utc::now().year();
+    let mut years = BTreeMap::<i32, Vec<BlogPost>>::new();
+    for summary in list_blogs(&args.path).context("Unable to read blog directory")? {
+        let loaded = find_blog(args, &summary.name)
+            .map_err(anyhow::Error::from)
+            .and_then(|blog| Ok((blog.load()?, blog)));
+        let (posts, blog) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                log::error!("Unable to load {}: {:#}", summary.name, e);
+                continue;
+            }
+        };
+        for mut post in posts {
+            let Some(date) = post.common.parsed_date() else {
+                continue;
+            };
+            if date.month() == day.month && date.day() == day.day && date.year() < this_year {
+                // Only the media of the posts shown is described
+                sniff::describe_media(&blog, std::slice::from_mut(&mut post));
+                years.entry(date.year()).or_default().push(BlogPost {
+                    blog: summary.name.clone(),
+                    post,
+                });
+            }
+        }
+    }
+    Ok(years
+        .into_iter()
+        .rev()
+        .map(|(year, mut posts)| {
+            posts.sort_by_key(|post| post.post.common.parsed_date());
+            YearPosts { year, posts }
+        })
+        .collect())
+}
+
+/// Print the posts from every blog made on the day of the year in previous years
+pub fn print_on_this_day(args: &Args, on_this_day_args: &OnThisDayArgs) -> anyhow::Result<()> {
+    let day = on_this_day_args.date.unwrap_or_else(MonthDay::today);
+    let years = on_this_day(args, day)?;
+    if years.is_empty() {
+        println!(
+            "No posts on {:02}-{:02} in previous years",
+            day.month, day.day
+        );
+    }
+    for year in years {
+        println!("{}", year.year);
+        for BlogPost { blog, post } in &year.posts {
+            let description = post
+                .title()
+                .or(post.common.slug())
+                .or(post.common.post_url.as_deref())
+                .map(ToOwned::to_owned)
+                .unwrap_or_else(|| post.common.id.to_string());
+            println!("  {} ({}): {}", blog, post.r#type.name(), description);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::blog::{find_blogs, BlogKind};
+use crate::blog_dir::BlogDir;
+use crate::text_parser::split_text_posts;
+use crate::{find_blog, Args, MetadataType};
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::Path;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConvertArgs {
+    /// Names of the blogs to convert, defaults to all blogs
+    blogs: Vec<String>,
+}
+
+/// Convert text format metadata files to the JSON format
+pub fn convert(args: &Args, convert_args: &ConvertArgs) -> anyhow::Result<()> {
+    let blogs = if convert_args.blogs.is_empty() {
+        find_blogs(&args.path).context("Unable to read blog directory")?
+    } else {
+        convert_args.blogs.clone()
+    };
+    for blog in blogs {
+        let blog = find_blog(args, &blog)?;
+        if blog.kind != BlogKind::TumblThree {
+            continue;
+        }
+        if blog.is_archive() {
+            log::warn!(
+                "Skipping {}, blogs within archives can't be converted",
+                blog.name
+            );
+            continue;
+        }
+        let dir = blog.path;
+        for metadata_type in enum_iterator::all::<MetadataType>() {
+            convert_file(&dir, metadata_type)
+                .with_context(|| format!("{}/{}", blog.name, metadata_type.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert a metadata file (if it exists and isn't already JSON), the original is kept with
+/// a `.bak` extension
+fn convert_file(dir: &Path, metadata_type: MetadataType) -> anyhow::Result<()> {
+    let path = dir.join(metadata_type.file_name());
+    if !path.is_file() {
+        return Ok(());
+    }
+    let text = fs::read_to_string(&path)?;
+    if text.starts_with('[') {
+        log::info!("Skipping {}, already in JSON format", path.display());
+        return Ok(());
+    }
+    let blog_dir = BlogDir::new(dir);
+    let posts = split_text_posts(text)
+        .into_iter()
+        .map(|text| metadata_type.convert_text(text, &blog_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let backup = path.with_extension("txt.bak");
+    if backup.exists() {
+        bail!("Backup file {} already exists", backup.display());
+    }
+    fs::copy(&path, &backup)?;
+    fs::write(&path, serde_json::to_string_pretty(&posts)?)?;
+    log::info!(
+        "Converted {} posts in {}, the original was saved to {}",
+        posts.len(),
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
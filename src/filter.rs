@@ -0,0 +1,107 @@
+use crate::model::{Post, PostType};
+use chrono::{Datelike, NaiveDateTime};
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+/// Query parameters selecting a subset of a blog's posts, matching the viewer's filters
+#[derive(Deserialize, Debug, Default)]
+pub struct PostFilter {
+    /// One of the types in [Post::types]
+    #[serde(rename = "type")]
+    post_type: Option<String>,
+    /// Text to search for within the tags, captions, titles and bodies
+    search: Option<String>,
+    /// A tag the post must have
+    tag: Option<String>,
+    /// The year, month or day of the post e.g. `2020`, `2020-05` or `2020-05-17`
+    date: Option<String>,
+}
+
+impl PostFilter {
+    pub fn matches(&self, post: &Post) -> bool {
+        if let Some(post_type) = &self.post_type {
+            if !post.types().contains(&post_type.as_str()) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !post.common.tags.contains(tag) {
+                return false;
+            }
+        }
+        if let Some(date) = &self.date {
+            if !post
+                .common
+                .parsed_date()
+                .is_some_and(|post_date| matches_date(post_date, date))
+            {
+                return false;
+            }
+        }
+        match &self.search {
+            Some(search) if !search.is_empty() => matches_search(post, search),
+            _ => true,
+        }
+    }
+}
+
+/// Query parameters for a random ordering of a blog's posts
+#[derive(Deserialize, Debug, Default)]
+pub struct Shuffle {
+    /// The same seed always gives the same order, so a shuffled listing can be paged through
+    /// or shared. An empty seed, or one that isn't a number, is the same as no seed
+    seed: Option<String>,
+}
+
+impl Shuffle {
+    fn seed(&self) -> Option<u64> {
+        self.seed.as_deref().and_then(|seed| seed.parse().ok())
+    }
+
+    /// Shuffle the posts if a seed was given
+    pub fn apply(&self, posts: &mut [Post]) {
+        if let Some(seed) = self.seed() {
+            shuffle(posts, seed);
+        }
+    }
+
+    /// Pick a post at random, or by the seed if one was given. The posts must be in the order
+    /// they were loaded in, sorted by id, for the same seed to pick the same post
+    pub fn choose(&self, posts: impl Iterator<Item = Post>) -> Option<Post> {
+        let seed = self.seed().unwrap_or_else(rand::random);
+        posts.choose(&mut ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+/// Shuffle posts in an order that only depends on the seed and the ids of the posts, not on
+/// the order they were loaded in
+fn shuffle(posts: &mut [Post], seed: u64) {
+    posts.sort_by_key(|post| post.common.id);
+    posts.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+}
+
+fn matches_search(post: &Post, search: &str) -> bool {
+    let contains = |text: &Option<String>| text.as_deref().unwrap_or_default().contains(search);
+    post.common.tags.join(", ").contains(search)
+        || match &post.r#type {
+            PostType::Image(image) => contains(&image.caption),
+            PostType::Video(video) => contains(&video.caption),
+            PostType::Text(text) => contains(&text.title) || text.body.contains(search),
+            PostType::Answer(answer) => contains(&answer.body),
+        }
+}
+
+/// Whether a date is within a period given as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`
+fn matches_date(date: NaiveDateTime, period: &str) -> bool {
+    let parts = period.split('-').map(str::parse::<i64>).collect::<Vec<_>>();
+    if parts.is_empty() || parts.len() > 3 {
+        return false;
+    }
+    let actual = [date.year() as i64, date.month() as i64, date.day() as i64];
+    parts
+        .iter()
+        .zip(actual)
+        .all(|(part, actual)| part.as_ref().is_ok_and(|part| *part == actual))
+}
@@ -0,0 +1,141 @@
+//! Reads folders downloaded by gallery-dl with `--write-metadata`, which writes a JSON sidecar
+//! file named `<media file>.json` next to each media file
+
+use crate::blog::Blog;
+use crate::blog_dir::BlogDir;
+use crate::json_parser::remove_body_media;
+use crate::model::{Image, Media, MediaKind, Post, PostCommon, PostType, Text, Video};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const SIDECAR_EXTENSION: &str = ".json";
+const VIDEO_EXTENSIONS: &[&str] = &[".mp4", ".webm", ".mov"];
+
+/// Whether a directory contains media downloaded by gallery-dl
+pub fn is_gallery_dir(path: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(path) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        name.strip_suffix(SIDECAR_EXTENSION)
+            .is_some_and(|media| path.join(media).is_file())
+    })
+}
+
+/// The metadata written by gallery-dl's Tumblr extractor for each media file
+#[derive(Deserialize)]
+struct Sidecar {
+    category: Option<String>,
+    id: u64,
+    #[serde(rename = "type")]
+    post_type: Option<String>,
+    post_url: Option<String>,
+    date: Option<serde_json::Value>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Index of the file within the post
+    #[serde(default)]
+    num: u32,
+    caption: Option<String>,
+    title: Option<String>,
+    body: Option<String>,
+    /// Dimensions of photos
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Loads all posts from the sidecar files, sorted by id, sidecars that can't be read are skipped
+/// unless `strict`
+pub fn load(blog: &Blog, strict: bool) -> anyhow::Result<Vec<Post>> {
+    let blog_dir = BlogDir::new(&blog.path);
+    let mut posts = BTreeMap::<u64, Vec<(Sidecar, &str)>>::new();
+    for file in &blog_dir.files {
+        let Some(media) = file.strip_suffix(SIDECAR_EXTENSION) else {
+            continue;
+        };
+        if !blog_dir.contains(media) {
+            continue;
+        }
+        let sidecar = fs::read_to_string(blog_dir.path.join(file))
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Ok(serde_json::from_str::<Sidecar>(&text)?));
+        let sidecar = match sidecar {
+            Ok(sidecar) => sidecar,
+            Err(e) if strict => return Err(e.context(file.clone())),
+            Err(e) => {
+                log::warn!("Skipping sidecar {}: {:#}", file, e);
+                continue;
+            }
+        };
+        if sidecar.category.as_deref().is_some_and(|c| c != "tumblr") {
+            continue;
+        }
+        posts.entry(sidecar.id).or_default().push((sidecar, media));
+    }
+    Ok(posts
+        .into_values()
+        .map(|mut files| {
+            files.sort_by_key(|(sidecar, _)| sidecar.num);
+            into_post(files, &blog_dir)
+        })
+        .collect())
+}
+
+/// Combine the sidecars of all files from the same post
+fn into_post(files: Vec<(Sidecar, &str)>, blog_dir: &BlogDir) -> Post {
+    let media = files
+        .iter()
+        .map(|(sidecar, file)| {
+            let kind = if VIDEO_EXTENSIONS.iter().any(|ext| file.ends_with(ext)) {
+                MediaKind::Video
+            } else {
+                MediaKind::from_file_name(file)
+            };
+            Media {
+                width: sidecar.width,
+                height: sidecar.height,
+                ..Media::from_matches(kind, None, None, vec![blog_dir.file_url(file)])
+            }
+        })
+        .collect::<Vec<_>>();
+    let video = media
+        .iter()
+        .find(|media| media.kind == MediaKind::Video)
+        .cloned()
+        .map(Box::new);
+    let sidecar = files.into_iter().next().unwrap().0;
+    let common = PostCommon {
+        id: sidecar.id,
+        post_url: sidecar.post_url,
+        date: sidecar.date.map(|date| match date {
+            serde_json::Value::String(date) => date,
+            other => other.to_string(),
+        }),
+        tags: sidecar.tags,
+    };
+    let r#type = match sidecar.post_type.as_deref() {
+        Some("photo") => PostType::Image(Image {
+            photos: media,
+            caption: sidecar.caption,
+        }),
+        Some("video") if video.is_some() => PostType::Video(Video {
+            video,
+            caption: sidecar.caption,
+        }),
+        _ => PostType::Text(Text {
+            title: sidecar.title,
+            body: remove_body_media(
+                sidecar
+                    .body
+                    .or(sidecar.caption)
+                    .as_deref()
+                    .unwrap_or_default(),
+            ),
+            media,
+        }),
+    };
+    Post { common, r#type }
+}
@@ -1,10 +1,13 @@
-use crate::model::{Answer, Image, Post, PostCommon, PostType, Text, Video, UNKNOWN_FILE};
-use crate::utils::{create_file_url, BlogDir};
+use crate::blog_dir::{file_name, BlogDir};
+use crate::model::{Answer, Image, Media, MediaKind, Post, PostCommon, PostType, Text, Video};
 use crate::MetadataType;
 use itertools::Itertools;
 use lol_html::{element, RewriteStrSettings};
 use serde::Deserialize;
 
+/// Why media is missing when no file was recorded as downloaded for it
+const NOT_RECORDED: &str = "No file was recorded as downloaded";
+
 #[derive(Deserialize)]
 struct JsonCommon {
     id: String,
@@ -43,14 +46,14 @@ impl JsonVideo {
                 self.common.id
             );
         }
+        let video = match self.common.downloaded_media_files.first() {
+            Some(filename) => media_for_file(blog_dir, filename, MediaKind::Video),
+            None => Media::missing(MediaKind::Video, None, String::from(NOT_RECORDED)),
+        };
         Ok(Post {
             common: self.common.to_model()?,
             r#type: PostType::Video(Video {
-                url: self
-                    .common
-                    .downloaded_media_files
-                    .first()
-                    .map(|filename| url_for_media_file(blog_dir, filename)),
+                video: Some(Box::new(video)),
                 caption: self.caption,
             }),
         })
@@ -63,29 +66,57 @@ struct JsonImage {
     common: JsonCommon,
     #[serde(alias = "photo-caption")]
     caption: Option<String>,
+    #[serde(rename = "photo-url-1280")]
+    photo_url: Option<String>,
+    /// The photos of photo sets, single photos only have the `photo-url-1280`
+    #[serde(default)]
+    photos: Vec<JsonPhoto>,
+}
+
+#[derive(Deserialize)]
+struct JsonPhoto {
+    #[serde(rename = "photo-url-1280")]
+    url: String,
 }
 
 impl JsonImage {
     fn into_post(self, blog_dir: &BlogDir) -> anyhow::Result<Post> {
-        if self.common.downloaded_media_files.is_empty() {
+        let photos = if self.common.downloaded_media_files.is_empty() {
             log::warn!(
                 "Missing downloaded_media_files for image {}",
                 self.common.id
             );
-        }
+            self.missing_photos()
+        } else {
+            self.common
+                .downloaded_media_files
+                .iter()
+                .map(|filename| media_for_file(blog_dir, filename, MediaKind::Image))
+                .collect()
+        };
         Ok(Post {
             common: self.common.to_model()?,
             r#type: PostType::Image(Image {
-                photo_urls: self
-                    .common
-                    .downloaded_media_files
-                    .iter()
-                    .map(|filename| url_for_media_file(blog_dir, filename))
-                    .collect(),
+                photos,
                 caption: self.caption,
             }),
         })
     }
+
+    /// The photos of a post without any downloaded files, by their original URLs
+    fn missing_photos(&self) -> Vec<Media> {
+        let mut urls = self
+            .photos
+            .iter()
+            .map(|photo| Some(photo.url.as_str()))
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            urls.push(self.photo_url.as_deref());
+        }
+        urls.into_iter()
+            .map(|url| Media::missing(MediaKind::Image, url, String::from(NOT_RECORDED)))
+            .collect()
+    }
 }
 
 #[derive(Deserialize)]
@@ -102,36 +133,15 @@ struct JsonText {
 impl JsonText {
     fn into_post(self, blog_dir: &BlogDir) -> anyhow::Result<Post> {
         let common = self.common.to_model()?;
-        // A text post may have images and videos within the body
-        // We must rewrite the body HTML to remove the remote URLs
-        let element_content_handlers = vec![
-            element!("img", |el| {
-                el.remove();
-                Ok(())
-            }),
-            element!("figure", |el| {
-                el.remove();
-                Ok(())
-            }),
-            element!("video", |el| {
-                el.remove();
-                Ok(())
-            }),
-        ];
-        let body = lol_html::rewrite_str(
-            &self.body,
-            RewriteStrSettings {
-                element_content_handlers,
-                ..RewriteStrSettings::default()
-            },
-        )
-        .unwrap();
+        let body = remove_body_media(&self.body);
         let media = self
             .common
             .downloaded_media_files
             .into_iter()
             .unique()
-            .map(|filename| url_for_media_file(blog_dir, &filename))
+            .map(|filename| {
+                media_for_file(blog_dir, &filename, MediaKind::from_file_name(&filename))
+            })
             .collect();
 
         Ok(Post {
@@ -139,7 +149,7 @@ impl JsonText {
             r#type: PostType::Text(Text {
                 title: self.title,
                 body,
-                media_urls: media,
+                media,
             }),
         })
     }
@@ -149,20 +159,55 @@ impl JsonText {
 struct JsonAnswer {
     #[serde(flatten)]
     common: JsonCommon,
-    question: String,
+    /// Missing from answers converted from the text format, which doesn't separate the question
+    question: Option<String>,
     answer: String,
 }
 
 impl JsonAnswer {
     fn into_post(self) -> anyhow::Result<Post> {
-        let answer = format!("<em>{}</em><br>{}", self.question, self.answer);
+        let answer = match self.question {
+            Some(question) => format!("<em>{}</em><br>{}", question, self.answer),
+            None => self.answer,
+        };
         Ok(Post {
             common: self.common.to_model()?,
-            r#type: PostType::Answer(Answer { body: Some(answer) }),
+            r#type: PostType::Answer(Answer {
+                body: Some(answer),
+                media: vec![],
+            }),
         })
     }
 }
 
+/// A text post may have images and videos within the body
+/// We must rewrite the body HTML to remove the remote URLs, the downloaded files are listed
+/// separately
+pub fn remove_body_media(body: &str) -> String {
+    let element_content_handlers = vec![
+        element!("img", |el| {
+            el.remove();
+            Ok(())
+        }),
+        element!("figure", |el| {
+            el.remove();
+            Ok(())
+        }),
+        element!("video", |el| {
+            el.remove();
+            Ok(())
+        }),
+    ];
+    lol_html::rewrite_str(
+        body,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::default()
+        },
+    )
+    .unwrap()
+}
+
 impl MetadataType {
     /// Parse a JSON format post
     pub fn parse_json(self, json: serde_json::Value, blog_dir: &BlogDir) -> anyhow::Result<Post> {
@@ -175,21 +220,38 @@ impl MetadataType {
     }
 }
 
-/// Resolve a file URL for an item in the `downloaded_media_files` array
-fn url_for_media_file(blog_dir: &BlogDir, downloaded_media_file: &str) -> String {
-    let mut search_prefix = downloaded_media_file;
+/// Resolve the media for an item in the `downloaded_media_files` array
+fn media_for_file(blog_dir: &BlogDir, downloaded_media_file: &str, kind: MediaKind) -> Media {
+    Media::from_matches(
+        kind,
+        Some(downloaded_media_file),
+        Some(search_prefix(downloaded_media_file)),
+        urls_for_media_file(blog_dir, downloaded_media_file),
+    )
+}
+
+/// The file name prefix searched for an item in the `downloaded_media_files` array
+fn search_prefix(downloaded_media_file: &str) -> &str {
     // Trim the extension to workaround https://github.com/TumblThreeApp/TumblThree/issues/439
-    if let Some(dot_index) = downloaded_media_file.rfind('.') {
-        search_prefix = &downloaded_media_file[0..dot_index + 1];
+    match downloaded_media_file.rfind('.') {
+        Some(dot_index) => &downloaded_media_file[0..dot_index + 1],
+        None => downloaded_media_file,
     }
+}
 
-    if let Some(matched) = blog_dir.find_file_starting_with(search_prefix) {
-        if matched != downloaded_media_file {
+/// Resolve the URLs of all files matching an item in the `downloaded_media_files` array, best
+/// first
+fn urls_for_media_file(blog_dir: &BlogDir, downloaded_media_file: &str) -> Vec<String> {
+    let matches = blog_dir.find_files_starting_with(search_prefix(downloaded_media_file));
+    match matches.first() {
+        Some(matched) if file_name(matched) != file_name(downloaded_media_file) => {
             log::warn!("Rewriting file {} to {}", downloaded_media_file, matched);
         }
-        create_file_url(&blog_dir.path, &matched)
-    } else {
-        log::warn!("Unable to find file matching {}", downloaded_media_file);
-        String::from(UNKNOWN_FILE)
+        Some(_) => {}
+        None => log::warn!("Unable to find file matching {}", downloaded_media_file),
     }
+    matches
+        .iter()
+        .map(|matched| blog_dir.file_url(matched))
+        .collect()
 }
@@ -1,29 +1,52 @@
+mod api_v2;
+mod archive;
+mod blog;
+mod blog_dir;
+mod calendar;
+mod convert;
+mod filter;
+mod gallery_dl;
 mod json_parser;
+mod missing;
 mod model;
+mod mp4;
+mod npf;
+mod orphans;
+mod probe;
+mod settings;
+mod sniff;
+mod static_site;
+mod stats;
+mod stream;
 mod text_parser;
+mod thumbnail;
+mod tumblr_export;
+mod tumblr_utils;
 mod utils;
+mod wxr;
+mod zip_export;
 
+use crate::blog::{list_blogs, Blog, BlogKind};
+use crate::filter::{PostFilter, Shuffle};
 use crate::model::Post;
 use crate::text_parser::split_text_posts;
 use actix_cors::Cors;
-use actix_web::http::StatusCode;
+use actix_web::body::BodySize;
+use actix_web::http::{header, StatusCode};
 use actix_web::web::Data;
 use actix_web::{get, web, App, HttpResponse, HttpServer};
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use enum_iterator::Sequence;
 use env_logger::Env;
 use rust_embed::RustEmbed;
-use std::path::{Path, PathBuf};
-use std::{fs, io};
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
 use thiserror::Error;
 use tokio::select;
 
-const VIDEOS_FILENAME: &str = "videos.txt";
-const IMAGES_FILENAME: &str = "images.txt";
-const TEXTS_FILENAME: &str = "texts.txt";
-const ANSWERS_FILENAME: &str = "answers.txt";
-
 #[derive(Copy, Clone, Sequence)]
 enum MetadataType {
     Videos,
@@ -51,38 +74,29 @@ struct Viewer;
 #[folder = "index/"]
 struct Index;
 
-/// Returns list of all directories that contain one or more TumblThree metadata files
+#[derive(Deserialize)]
+struct BlogsQuery {
+    /// Whether to load every blog to compare it with its TumblThree settings
+    #[serde(default)]
+    completeness: bool,
+}
+
+/// Returns list of all directories that contain one or more TumblThree metadata files, and
+/// any other supported backups, along with the blog settings from TumblThree's Index folder
 #[get("/blogs")]
-async fn blogs(args: Data<Args>) -> HttpResponse {
+async fn blogs(args: Data<Args>, query: web::Query<BlogsQuery>) -> HttpResponse {
     let blogs = web::block(move || -> Result<_, String> {
-        Ok(fs::read_dir(&args.path)
-            .map_err(|_| String::from("Unable to read blog directory"))?
-            .filter_map(Result::ok)
-            .filter_map(|dir| {
-                if !dir.path().is_dir() {
-                    return None;
-                }
-                let name = match dir.path().file_name() {
-                    None => return None,
-                    Some(name) => name.to_string_lossy().into_owned(),
-                };
-                if name == "Index" {
-                    return None;
+        let mut blogs =
+            list_blogs(&args.path).map_err(|_| String::from("Unable to read blog directory"))?;
+        if query.completeness {
+            for blog in &mut blogs {
+                match stats::blog_completeness(&args, blog) {
+                    Ok(completeness) => blog.completeness = Some(completeness),
+                    Err(e) => log::error!("Unable to load {}: {:#}", blog.name, e),
                 }
-                if ![
-                    VIDEOS_FILENAME,
-                    IMAGES_FILENAME,
-                    TEXTS_FILENAME,
-                    ANSWERS_FILENAME,
-                ]
-                .iter()
-                .any(|file| dir.path().join(file).exists())
-                {
-                    return None;
-                }
-                Some(name)
-            })
-            .collect::<Vec<_>>())
+            }
+        }
+        Ok(blogs)
     })
     .await
     .unwrap();
@@ -104,54 +118,352 @@ enum BlogError {
     ),
 }
 
-/// Return a list of posts
+impl BlogError {
+    fn into_response(self) -> HttpResponse {
+        log::error!("{}", self);
+        match self {
+            BlogError::NotFound => HttpResponse::NotFound().body(format!("{}", self)),
+            BlogError::Internal(_) => HttpResponse::BadRequest().body(format!("{}", self)),
+        }
+    }
+}
+
+/// Resolve a blog within the base directory
+fn find_blog(args: &Args, blog_name: &str) -> Result<Blog, BlogError> {
+    let path = args
+        .path
+        .canonicalize()
+        .expect("unable to canonicalize")
+        .join(blog_name);
+    let kind = BlogKind::detect(&path).ok_or(BlogError::NotFound)?;
+    Ok(Blog {
+        name: blog_name.to_string(),
+        path,
+        kind,
+        media_url: format!("http://localhost:{}/blogs/{}/media", args.port, blog_name),
+        thumbnail_url: format!(
+            "http://localhost:{}/blogs/{}/thumbnails",
+            args.port, blog_name
+        ),
+        poster_url: format!("http://localhost:{}/blogs/{}/posters", args.port, blog_name),
+    })
+}
+
+/// Return a list of the posts matching the filter, shuffled if a seed is given
 #[get("/blogs/{name}")]
-async fn blog(args: Data<Args>, blog_name: web::Path<String>) -> HttpResponse {
+async fn blog_posts(
+    args: Data<Args>,
+    blog_name: web::Path<String>,
+    filter: web::Query<PostFilter>,
+    shuffle: web::Query<Shuffle>,
+) -> HttpResponse {
     let res = web::block(move || -> Result<_, BlogError> {
-        let dir = args
-            .path
-            .canonicalize()
-            .expect("unable to canonicalize")
-            .join(blog_name.into_inner());
-        if !dir.is_dir() {
-            return Err(BlogError::NotFound);
-        }
-        let mut posts = Vec::new();
-        for file in enum_iterator::all::<MetadataType>() {
-            posts.extend(load_posts(&dir, file).context(file.file_name())?);
-        }
-        posts.sort_by_key(|p| p.common.id);
+        let blog = find_blog(&args, &blog_name)?;
+        let mut posts = blog.load()?;
+        shuffle.apply(&mut posts);
+        posts.retain(|post| filter.matches(post));
+        sniff::describe_media(&blog, &mut posts);
         Ok(posts)
     })
     .await
     .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(res) => HttpResponse::Ok().json(res),
+    }
+}
+
+/// Return a random post matching the filter, the same post is returned for the same seed
+#[get("/blogs/{name}/random")]
+async fn blog_random(
+    args: Data<Args>,
+    blog_name: web::Path<String>,
+    filter: web::Query<PostFilter>,
+    shuffle: web::Query<Shuffle>,
+) -> HttpResponse {
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let posts = blog.load()?;
+        let mut post = shuffle.choose(posts.into_iter().filter(|post| filter.matches(post)));
+        if let Some(post) = &mut post {
+            sniff::describe_media(&blog, std::slice::from_mut(post));
+        }
+        Ok(post)
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(None) => HttpResponse::NotFound().body("No posts found"),
+        Ok(Some(post)) => HttpResponse::Ok().json(post),
+    }
+}
+
+/// Return statistics of the posts of a blog
+#[get("/blogs/{name}/stats")]
+async fn blog_stats(args: Data<Args>, blog_name: web::Path<String>) -> HttpResponse {
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let mut posts = blog.load()?;
+        sniff::describe_media(&blog, &mut posts);
+        Ok(stats::BlogStats::new(&posts))
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(res) => HttpResponse::Ok().json(res),
+    }
+}
+
+#[derive(Deserialize)]
+struct MissingQuery {
+    /// `json` or `csv`, defaults to JSON
+    format: Option<missing::ReportFormat>,
+}
+
+/// Return the media of a blog that couldn't be found, with the original URLs and the file names
+/// that were searched for, as JSON or CSV
+#[get("/blogs/{name}/missing")]
+async fn blog_missing(
+    args: Data<Args>,
+    blog_name: web::Path<String>,
+    query: web::Query<MissingQuery>,
+) -> HttpResponse {
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let posts = blog.load()?;
+        Ok(missing::missing_media(&posts))
+    })
+    .await
+    .unwrap();
+    match (res, query.format) {
+        (Err(e), _) => e.into_response(),
+        (Ok(res), Some(missing::ReportFormat::Csv)) => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(missing::to_csv(&res)),
+        (Ok(res), _) => HttpResponse::Ok().json(res),
+    }
+}
+
+/// Return the media files of a blog that aren't referenced by any post, with their sizes
+#[get("/blogs/{name}/orphans")]
+async fn blog_orphans(args: Data<Args>, blog_name: web::Path<String>) -> HttpResponse {
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let posts = blog.load()?;
+        Ok(orphans::OrphanReport::new(&blog, &posts)?)
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(res) => HttpResponse::Ok().json(res),
+    }
+}
+
+/// Return the number of posts by year, month and day
+#[get("/blogs/{name}/archive")]
+async fn blog_archive(args: Data<Args>, blog_name: web::Path<String>) -> HttpResponse {
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        Ok(calendar::PostArchive::new(&blog.load()?))
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(res) => HttpResponse::Ok().json(res),
+    }
+}
+
+#[derive(Deserialize)]
+struct OnThisDayQuery {
+    /// Month and day to show posts from as `MM-DD`, defaults to today
+    date: Option<calendar::MonthDay>,
+}
+
+/// Return the posts from every blog made on the same day in previous years, grouped by year
+#[get("/on-this-day")]
+async fn on_this_day(args: Data<Args>, query: web::Query<OnThisDayQuery>) -> HttpResponse {
+    let day = query.date.unwrap_or_else(calendar::MonthDay::today);
+    let res = web::block(move || calendar::on_this_day(&args, day))
+        .await
+        .unwrap();
     match res {
         Err(e) => {
-            log::error!("{}", e);
-            match e {
-                BlogError::NotFound => HttpResponse::NotFound().body(format!("{}", e)),
-                BlogError::Internal(_) => HttpResponse::BadRequest().body(format!("{}", e)),
-            }
+            log::error!("{:#}", e);
+            HttpResponse::InternalServerError().body(format!("{:#}", e))
         }
         Ok(res) => HttpResponse::Ok().json(res),
     }
 }
 
+/// Download a ZIP of the posts matching the filter along with their media files
+#[get("/blogs/{name}/zip")]
+async fn blog_zip(
+    args: Data<Args>,
+    blog_name: web::Path<String>,
+    filter: web::Query<PostFilter>,
+) -> HttpResponse {
+    let blog_name = blog_name.into_inner();
+    let name = blog_name.clone();
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &name)?;
+        let mut posts = blog
+            .load()?
+            .into_iter()
+            .filter(|post| filter.matches(post))
+            .collect::<Vec<_>>();
+        sniff::describe_media(&blog, &mut posts);
+        Ok((blog, posts))
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok((blog, posts)) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.zip\"", blog_name),
+            ))
+            .body(zip_export::stream(blog, posts)),
+    }
+}
+
+/// Serve a file from within a blog, for media that can't be referenced directly on disk
+#[get("/blogs/{name}/media/{file:.*}")]
+async fn blog_media(args: Data<Args>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (blog_name, file) = path.into_inner();
+    let mime = file.rfind('.').map(|idx| {
+        let ext = &file[idx + 1..];
+        actix_files::file_extension_to_mime(ext)
+    });
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let Some((mut reader, size)) = blog.open_file(&file)? else {
+            return Ok(None);
+        };
+        // The type is detected from the contents, as files may have the wrong extension
+        let mut header = Vec::with_capacity(sniff::HEADER_LEN);
+        (&mut reader)
+            .take(sniff::HEADER_LEN as u64)
+            .read_to_end(&mut header)
+            .context("Unable to read media file")?;
+        let sniffed = sniff::sniff(&header);
+        Ok(Some((io::Cursor::new(header).chain(reader), size, sniffed)))
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Ok(Some((mut reader, size, sniffed))) => HttpResponse::Ok()
+            .content_type(match sniffed {
+                Some(sniffed) => sniffed.to_string(),
+                None => mime.unwrap_or(mime::APPLICATION_OCTET_STREAM).to_string(),
+            })
+            .body(stream::spawn_body(BodySize::Sized(size), move |mut out| {
+                io::copy(&mut reader, &mut out)?;
+                out.flush()?;
+                Ok(())
+            })),
+    }
+}
+
+/// How long browsers may cache thumbnails and posters for
+const IMAGE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+fn cache_control() -> header::CacheControl {
+    header::CacheControl(vec![
+        header::CacheDirective::Public,
+        header::CacheDirective::MaxAge(IMAGE_MAX_AGE),
+    ])
+}
+
+/// Serve a JPEG thumbnail of an image within a blog, at one of the supported widths
+#[get("/blogs/{name}/thumbnails/{width}/{file:.*}")]
+async fn blog_thumbnail(args: Data<Args>, path: web::Path<(String, u32, String)>) -> HttpResponse {
+    let (blog_name, width, file) = path.into_inner();
+    if !thumbnail::WIDTHS.contains(&width) {
+        return HttpResponse::BadRequest().body(format!(
+            "Unsupported thumbnail width {}, expected one of {:?}",
+            width,
+            thumbnail::WIDTHS
+        ));
+    }
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        let cache_dir = args
+            .cache_dir
+            .clone()
+            .unwrap_or_else(thumbnail::default_cache_dir);
+        match thumbnail::thumbnail(&blog, &file, width, &cache_dir)? {
+            Some(cached) => Ok(Some(fs::read(cached).context("Unable to read thumbnail")?)),
+            None => Ok(None),
+        }
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Ok(Some(data)) => HttpResponse::Ok()
+            .content_type(mime::IMAGE_JPEG)
+            .insert_header(cache_control())
+            .body(data),
+    }
+}
+
+/// Serve the cover art embedded in a video within a blog
+#[get("/blogs/{name}/posters/{file:.*}")]
+async fn blog_poster(args: Data<Args>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (blog_name, file) = path.into_inner();
+    let res = web::block(move || -> Result<_, BlogError> {
+        let blog = find_blog(&args, &blog_name)?;
+        Ok(mp4::cover(&blog, &file)?)
+    })
+    .await
+    .unwrap();
+    match res {
+        Err(e) => e.into_response(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Ok(Some(data)) => HttpResponse::Ok()
+            .content_type(sniff::sniff(&data).unwrap_or("application/octet-stream"))
+            .insert_header(cache_control())
+            .body(data),
+    }
+}
+
+/// Loads all posts from every metadata file in a TumblThree blog, sorted by id
+fn load_blog(blog: &Blog) -> anyhow::Result<Vec<Post>> {
+    let blog_dir = blog.blog_dir()?;
+    let mut posts = Vec::new();
+    for file in enum_iterator::all::<MetadataType>() {
+        posts.extend(load_posts(blog, &blog_dir, file).context(file.file_name())?);
+    }
+    posts.sort_by_key(|p| p.common.id);
+    Ok(posts)
+}
+
 /// Loads all posts from a metadata file (if it exists)
-fn load_posts(dir: &Path, metadata_type: MetadataType) -> anyhow::Result<Vec<Post>> {
-    let path = dir.join(metadata_type.file_name());
-    if path.is_file() {
-        let text = fs::read_to_string(path)?;
-        let blog_dir = utils::BlogDir::new(dir);
+fn load_posts(
+    blog: &Blog,
+    blog_dir: &blog_dir::BlogDir,
+    metadata_type: MetadataType,
+) -> anyhow::Result<Vec<Post>> {
+    if let Some(data) = blog.read_file(metadata_type.file_name())? {
+        let text = String::from_utf8(data)?;
         if text.starts_with('[') {
             serde_json::from_str::<Vec<serde_json::Value>>(&text)?
                 .into_iter()
-                .map(|json| metadata_type.parse_json(json, &blog_dir))
+                .map(|json| metadata_type.parse_json(json, blog_dir))
                 .collect::<Result<Vec<_>, _>>()
         } else {
             split_text_posts(text)
                 .into_iter()
-                .map(|text| metadata_type.parse_text(text, &blog_dir))
+                .map(|text| metadata_type.parse_text(text, blog_dir))
                 .collect::<Result<Vec<_>, _>>()
         }
     } else {
@@ -179,15 +491,54 @@ struct Args {
     /// Port number to run web server on
     #[arg(long, default_value_t = 7100)]
     port: u16,
-    /// Your TumblThree blogs directory
-    #[arg(long, default_value = ".")]
+    /// Your TumblThree blogs directory, which may also contain other supported backups
+    #[arg(long, default_value = ".", global = true)]
     path: PathBuf,
+    /// Directory to cache image thumbnails in, defaults to a directory within the system's
+    /// temporary directory
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Export a blog to a WordPress eXtended RSS (WXR) file
+    ExportWxr(wxr::WxrArgs),
+    /// Export a blog as content for a static site generator
+    ExportSite(static_site::SiteArgs),
+    /// Convert text format metadata files to the JSON format
+    Convert(convert::ConvertArgs),
+    /// Show how completely each blog has been downloaded
+    Stats(stats::StatsArgs),
+    /// Show the posts from every blog made on this day in previous years
+    OnThisDay(calendar::OnThisDayArgs),
+    /// List the media files of a blog that aren't referenced by any post
+    Orphans(orphans::OrphansArgs),
+    /// Report the media of a blog that couldn't be found, to download again
+    Missing(missing::MissingArgs),
 }
 
 #[actix_web::main]
-async fn main() -> io::Result<()> {
+async fn main() -> anyhow::Result<()> {
     let args: Args = Args::parse();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    match &args.command {
+        None => Ok(serve(args).await?),
+        Some(Command::ExportWxr(wxr_args)) => wxr::export(&args, wxr_args),
+        Some(Command::ExportSite(site_args)) => static_site::export(&args, site_args),
+        Some(Command::Convert(convert_args)) => convert::convert(&args, convert_args),
+        Some(Command::Stats(stats_args)) => stats::print_stats(&args, stats_args),
+        Some(Command::OnThisDay(on_this_day_args)) => {
+            calendar::print_on_this_day(&args, on_this_day_args)
+        }
+        Some(Command::Orphans(orphans_args)) => orphans::print_orphans(&args, orphans_args),
+        Some(Command::Missing(missing_args)) => missing::print_missing(&args, missing_args),
+    }
+}
+
+async fn serve(args: Args) -> io::Result<()> {
     log::info!(
         "Using base directory: {}",
         args.path.canonicalize().unwrap().display()
@@ -206,7 +557,17 @@ async fn main() -> io::Result<()> {
             .app_data(Data::new(args2.clone()))
             .wrap(cors)
             .service(blogs)
-            .service(blog)
+            .service(blog_posts)
+            .service(blog_random)
+            .service(blog_stats)
+            .service(blog_orphans)
+            .service(blog_missing)
+            .service(blog_archive)
+            .service(on_this_day)
+            .service(blog_zip)
+            .service(blog_media)
+            .service(blog_thumbnail)
+            .service(blog_poster)
             .service(viewer)
     })
     .bind(("127.0.0.1", args.port))?
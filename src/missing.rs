@@ -0,0 +1,116 @@
+//! Reports the media of a blog that couldn't be found on disk, with the original URLs and the
+//! file names that were searched for, so that the media can be downloaded again
+
+use crate::model::{MediaKind, Post};
+use crate::{find_blog, Args};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Deserialize, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MissingArgs {
+    /// Name of the blog to check
+    blog: String,
+    #[arg(long, value_enum, default_value_t = ReportFormat::Csv)]
+    format: ReportFormat,
+    /// File to write the report to, defaults to standard output
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MissingMedia {
+    pub post_id: u64,
+    pub post_url: Option<String>,
+    pub kind: MediaKind,
+    /// The URL the media was downloaded from, or the file name recorded when downloading it
+    pub original_url: Option<String>,
+    /// The file name prefix, or path, that was searched for
+    pub search: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// The media of the posts that couldn't be found
+pub fn missing_media(posts: &[Post]) -> Vec<MissingMedia> {
+    posts
+        .iter()
+        .flat_map(|post| {
+            post.media()
+                .into_iter()
+                .filter(|media| media.is_missing())
+                .map(|media| MissingMedia {
+                    post_id: post.common.id,
+                    post_url: post.common.post_url.clone(),
+                    kind: media.kind,
+                    original_url: media.original_url.clone(),
+                    search: media.search.clone(),
+                    reason: media.reason.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Format the missing media as CSV with a header row
+pub fn to_csv(missing: &[MissingMedia]) -> String {
+    let mut csv = String::from("post_id,post_url,kind,original_url,search,reason\n");
+    for media in missing {
+        let kind = match media.kind {
+            MediaKind::Image => "image",
+            MediaKind::Video => "video",
+            MediaKind::Audio => "audio",
+            MediaKind::Other => "other",
+        };
+        let fields = [
+            media.post_id.to_string(),
+            csv_field(media.post_url.as_deref()),
+            kind.to_string(),
+            csv_field(media.original_url.as_deref()),
+            csv_field(media.search.as_deref()),
+            csv_field(media.reason.as_deref()),
+        ];
+        writeln!(csv, "{}", fields.join(",")).unwrap();
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: Option<&str>) -> String {
+    let value = value.unwrap_or_default();
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Print or write the report of the missing media of a blog
+pub fn print_missing(args: &Args, missing_args: &MissingArgs) -> anyhow::Result<()> {
+    let blog = find_blog(args, &missing_args.blog)?;
+    let posts = blog.load()?;
+    let missing = missing_media(&posts);
+    let report = match missing_args.format {
+        ReportFormat::Csv => to_csv(&missing),
+        ReportFormat::Json => serde_json::to_string_pretty(&missing)? + "\n",
+    };
+    match &missing_args.output {
+        Some(output) => {
+            fs::write(output, report)?;
+            log::info!(
+                "Wrote {} missing media to {}",
+                missing.len(),
+                output.display()
+            );
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
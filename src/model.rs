@@ -1,7 +1,8 @@
+use crate::blog_dir::file_name;
+use crate::utils::{escape_html, parse_post_date};
+use chrono::NaiveDateTime;
 use serde::Serialize;
 
-pub const UNKNOWN_FILE: &str = "unknown";
-
 #[derive(Serialize, Debug)]
 pub struct Post {
     #[serde(flatten)]
@@ -27,15 +28,26 @@ pub enum PostType {
     Answer(Answer),
 }
 
+impl PostType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostType::Image(_) => "Image",
+            PostType::Video(_) => "Video",
+            PostType::Text(_) => "Text",
+            PostType::Answer(_) => "Answer",
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct Image {
-    pub photo_urls: Vec<String>,
+    pub photos: Vec<Media>,
     pub caption: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct Video {
-    pub url: Option<String>,
+    pub video: Option<Box<Media>>,
     pub caption: Option<String>,
 }
 
@@ -43,10 +55,322 @@ pub struct Video {
 pub struct Text {
     pub title: Option<String>,
     pub body: String,
-    pub media_urls: Vec<String>,
+    pub media: Vec<Media>,
+}
+
+/// A media file of a post, which may not have been found
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct Media {
+    /// URL of the file, if it was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Path of the file within the blog, if it was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The URL the media was downloaded from, or the file name recorded when downloading it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_url: Option<String>,
+    pub kind: MediaKind,
+    /// MIME type detected from the file contents, or else its extension
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Size of the file in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Number of frames of GIFs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<u32>,
+    /// Duration of videos in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// Codec of videos, e.g. `avc1` for H.264
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    /// Whether videos have an audio track
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_audio: Option<bool>,
+    /// URL of the cover art embedded in videos
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poster: Option<String>,
+    pub status: MediaStatus,
+    /// Why the media was substituted or couldn't be found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The file name prefix, or path, that was searched for, if the media couldn't be found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_text: Option<String>,
+    /// Whether the media is shown within the post body, rather than after it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub inline: bool,
+    /// URLs of other files matching the media, such as smaller sizes, best first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+    /// Resized copies of images, narrowest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub url: String,
+}
+
+#[derive(Serialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    #[default]
+    Other,
+}
+
+#[derive(Serialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaStatus {
+    /// The file was found with the name it was downloaded as
+    #[default]
+    Found,
+    /// A file with a different name was found, such as another size or extension
+    Substituted,
+    Missing,
+}
+
+impl MediaKind {
+    /// The kind of a media file by its extension
+    pub fn from_file_name(name: &str) -> Self {
+        let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+        match ext.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" | "png" | "pnj" | "gif" | "gifv" | "webp" | "bmp" => MediaKind::Image,
+            "mp4" | "mov" | "webm" => MediaKind::Video,
+            "mp3" | "m4a" | "ogg" | "wav" => MediaKind::Audio,
+            _ => MediaKind::Other,
+        }
+    }
+
+    /// The kind of a media file by its MIME type, if it is a media type
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type.split_once('/')?.0 {
+            "image" => Some(MediaKind::Image),
+            "video" => Some(MediaKind::Video),
+            "audio" => Some(MediaKind::Audio),
+            _ => None,
+        }
+    }
+}
+
+impl Media {
+    /// Media from the URLs of the files matching it, best first, using the best file and keeping
+    /// the others as alternatives
+    /// The media is substituted if the best file has a different name than the original, and
+    /// missing if no file starts with the searched prefix
+    pub fn from_matches(
+        kind: MediaKind,
+        original_url: Option<&str>,
+        search: Option<&str>,
+        mut urls: Vec<String>,
+    ) -> Self {
+        if urls.is_empty() {
+            let reason = match (search, original_url) {
+                (Some(search), _) => format!("No file found starting with {}", search),
+                (None, Some(original_url)) => format!("No file found matching {}", original_url),
+                (None, None) => String::from("No file was recorded"),
+            };
+            return Self {
+                search: search.map(ToOwned::to_owned),
+                ..Self::missing(kind, original_url, reason)
+            };
+        }
+        let url = urls.remove(0);
+        let original_name = original_url.map(|original_url| {
+            let path = original_url.split(['?', '#']).next().unwrap_or_default();
+            file_name(path)
+        });
+        let (status, reason) = match original_name {
+            Some(original_name) if original_name != file_name(&url) => (
+                MediaStatus::Substituted,
+                Some(format!(
+                    "{} wasn't found, using {}",
+                    original_name,
+                    file_name(&url)
+                )),
+            ),
+            _ => (MediaStatus::Found, None),
+        };
+        Self {
+            url: Some(url),
+            original_url: original_url.map(ToOwned::to_owned),
+            kind,
+            status,
+            reason,
+            alternatives: urls,
+            ..Self::default()
+        }
+    }
+
+    /// Media that couldn't be found
+    pub fn missing(kind: MediaKind, original_url: Option<&str>, reason: String) -> Self {
+        Self {
+            original_url: original_url.map(ToOwned::to_owned),
+            kind,
+            status: MediaStatus::Missing,
+            reason: Some(reason),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.status == MediaStatus::Missing
+    }
 }
 
 #[derive(Serialize, Debug)]
 pub struct Answer {
     pub body: Option<String>,
+    pub media: Vec<Media>,
+}
+
+impl PostCommon {
+    /// The post date, if present and in a recognised format
+    pub fn parsed_date(&self) -> Option<NaiveDateTime> {
+        self.date.as_deref().and_then(parse_post_date)
+    }
+
+    /// The path of the post URL e.g. `/post/123/the-slug`
+    pub fn url_path(&self) -> Option<&str> {
+        let url = self.post_url.as_deref()?;
+        let host_start = url.find("://").map(|idx| idx + 3).unwrap_or_default();
+        url[host_start..]
+            .find('/')
+            .map(|idx| &url[host_start + idx..])
+    }
+
+    /// The slug from the post URL e.g. `https://blog.tumblr.com/post/123/the-slug`
+    pub fn slug(&self) -> Option<&str> {
+        self.post_url
+            .as_deref()?
+            .split('/')
+            .skip_while(|part| *part != "post")
+            .nth(2)
+            .filter(|slug| !slug.is_empty())
+    }
+}
+
+impl Post {
+    pub fn title(&self) -> Option<&str> {
+        match &self.r#type {
+            PostType::Text(text) => text.title.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The types the post is listed under, text posts containing media are also listed as an
+    /// image or video post
+    pub fn types(&self) -> Vec<&'static str> {
+        let mut types = vec![self.r#type.name()];
+        if let PostType::Text(text) = &self.r#type {
+            if text
+                .media
+                .iter()
+                .any(|media| media.kind == MediaKind::Video)
+            {
+                types.push("Video");
+            } else if text
+                .media
+                .iter()
+                .any(|media| media.kind == MediaKind::Image)
+            {
+                types.push("Image");
+            }
+        }
+        types
+    }
+
+    /// All media of the post, whether or not it was found
+    pub fn media(&self) -> Vec<&Media> {
+        match &self.r#type {
+            PostType::Image(image) => image.photos.iter().collect(),
+            PostType::Video(video) => video.video.as_deref().into_iter().collect(),
+            PostType::Text(text) => text.media.iter().collect(),
+            PostType::Answer(answer) => answer.media.iter().collect(),
+        }
+    }
+
+    pub fn media_mut(&mut self) -> Vec<&mut Media> {
+        match &mut self.r#type {
+            PostType::Image(image) => image.photos.iter_mut().collect(),
+            PostType::Video(video) => video.video.as_deref_mut().into_iter().collect(),
+            PostType::Text(text) => text.media.iter_mut().collect(),
+            PostType::Answer(answer) => answer.media.iter_mut().collect(),
+        }
+    }
+
+    /// All resolved media files of the post
+    pub fn media_urls(&self) -> Vec<&str> {
+        self.media()
+            .into_iter()
+            .filter_map(|media| media.url.as_deref())
+            .collect()
+    }
+
+    /// Number of media files of the post that couldn't be found
+    pub fn unresolved_media(&self) -> usize {
+        self.media()
+            .into_iter()
+            .filter(|media| media.is_missing())
+            .count()
+    }
+
+    /// The HTML of the post body or caption, without the media listed separately
+    pub fn bodies(&self) -> Vec<&str> {
+        match &self.r#type {
+            PostType::Image(i) => i.caption.as_deref().into_iter().collect(),
+            PostType::Video(v) => v.caption.as_deref().into_iter().collect(),
+            PostType::Text(t) => vec![&t.body],
+            PostType::Answer(a) => a.body.as_deref().into_iter().collect(),
+        }
+    }
+
+    /// Render the post body as standalone HTML, media is referenced using the local file URLs
+    pub fn to_html(&self) -> String {
+        let render = |media: &Media| {
+            let url = escape_html(media.url.as_deref()?);
+            Some(match media.kind {
+                MediaKind::Video => format!("<video controls src=\"{}\"></video>", url),
+                MediaKind::Audio => format!("<audio controls src=\"{}\"></audio>", url),
+                MediaKind::Image | MediaKind::Other => format!(
+                    "<img src=\"{}\" alt=\"{}\">",
+                    url,
+                    escape_html(media.alt_text.as_deref().unwrap_or_default())
+                ),
+            })
+        };
+        let mut parts = Vec::new();
+        match &self.r#type {
+            PostType::Image(i) => {
+                parts.extend(i.photos.iter().filter_map(render));
+                parts.extend(i.caption.clone());
+            }
+            PostType::Video(v) => {
+                parts.extend(v.video.as_deref().and_then(render));
+                parts.extend(v.caption.clone());
+            }
+            PostType::Text(t) => {
+                parts.push(t.body.clone());
+                parts.extend(t.media.iter().filter(|m| !m.inline).filter_map(render));
+            }
+            PostType::Answer(a) => {
+                parts.extend(a.body.clone());
+                parts.extend(a.media.iter().filter(|m| !m.inline).filter_map(render));
+            }
+        }
+        parts.join("\n")
+    }
 }
@@ -0,0 +1,203 @@
+//! Reads the metadata of MP4 and QuickTime files from their container headers: the duration,
+//! resolution and codec of the video, whether there is an audio track and any embedded cover art
+//! The frames themselves are never decoded, so videos without cover art have no poster
+
+use crate::blog::Blog;
+use anyhow::bail;
+
+/// The MIME types of the files that can be read
+pub const SUPPORTED_TYPES: &[&str] = &["video/mp4", "video/quicktime", "video/3gpp", "audio/mp4"];
+/// The largest `moov` box that will be read, which holds the metadata and the sample tables
+const MAX_MOOV_LEN: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoInfo {
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// Display size, taking the rotation of the video track into account
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Sample entry type of the video track, e.g. `avc1` for H.264 or `hvc1` for HEVC
+    pub codec: Option<String>,
+    pub has_audio: bool,
+    pub has_cover: bool,
+}
+
+/// Read the metadata of an MP4 or QuickTime file within a blog, returns `None` if there is no
+/// such file or it has no `moov` box
+pub fn probe(blog: &Blog, file: &str) -> anyhow::Result<Option<VideoInfo>> {
+    let Some(moov) = read_moov(blog, file)? else {
+        return Ok(None);
+    };
+    let mut info = VideoInfo {
+        duration: find(&moov, b"mvhd").and_then(movie_duration),
+        has_cover: cover_art(&moov).is_some(),
+        ..VideoInfo::default()
+    };
+    for (_, trak) in Boxes::new(&moov).filter(|(kind, _)| kind == b"trak") {
+        let handler = find_path(trak, &[b"mdia", b"hdlr"]).and_then(|hdlr| hdlr.get(8..12));
+        match handler {
+            Some(b"vide") if info.codec.is_none() => {
+                let stsd = find_path(trak, &[b"mdia", b"minf", b"stbl", b"stsd"]);
+                info.codec = stsd
+                    .and_then(|stsd| stsd.get(12..16))
+                    .map(|codec| String::from_utf8_lossy(codec).trim().to_string());
+                let size = find(trak, b"tkhd")
+                    .and_then(track_size)
+                    .or_else(|| stsd.and_then(sample_entry_size));
+                if let Some((width, height)) = size {
+                    info.width = Some(width);
+                    info.height = Some(height);
+                }
+            }
+            Some(b"soun") => info.has_audio = true,
+            _ => {}
+        }
+    }
+    Ok(Some(info))
+}
+
+/// The cover art embedded in an MP4 or QuickTime file within a blog, returns `None` if there
+/// is no such file or it has no cover art
+pub fn cover(blog: &Blog, file: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(moov) = read_moov(blog, file)? else {
+        return Ok(None);
+    };
+    Ok(cover_art(&moov).map(ToOwned::to_owned))
+}
+
+/// Read the contents of the `moov` box, which may come before or after the media data
+fn read_moov(blog: &Blog, file: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(size) = blog.file_size(file)? else {
+        return Ok(None);
+    };
+    let mut offset = 0;
+    while offset < size {
+        let Some(header) = blog.read_range(file, offset, 16)? else {
+            return Ok(None);
+        };
+        let Some((len, header_len, kind)) = box_header(&header, size - offset) else {
+            return Ok(None);
+        };
+        if &kind == b"moov" {
+            if len > MAX_MOOV_LEN {
+                bail!("The moov box of {} is too large: {} bytes", file, len);
+            }
+            let content =
+                blog.read_range(file, offset + header_len, (len - header_len) as usize)?;
+            return Ok(content);
+        }
+        offset += len;
+    }
+    Ok(None)
+}
+
+/// The length of a box including its header, the length of its header, and its type
+/// Boxes may have a 64 bit length, or extend to the end of the file
+fn box_header(data: &[u8], remaining: u64) -> Option<(u64, u64, [u8; 4])> {
+    let len = u32::from_be_bytes(data.get(0..4)?.try_into().unwrap()) as u64;
+    let kind = data.get(4..8)?.try_into().unwrap();
+    let (len, header_len) = match len {
+        0 => (remaining, 8),
+        1 => (u64::from_be_bytes(data.get(8..16)?.try_into().unwrap()), 16),
+        len => (len, 8),
+    };
+    (len >= header_len && len <= remaining).then_some((len, header_len, kind))
+}
+
+/// The child boxes within the contents of a box
+struct Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Boxes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (len, header_len, kind) = box_header(self.data, self.data.len() as u64)?;
+        let content = &self.data[header_len as usize..len as usize];
+        self.data = &self.data[len as usize..];
+        Some((kind, content))
+    }
+}
+
+fn find<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    Boxes::new(data).find_map(|(k, content)| (&k == kind).then_some(content))
+}
+
+fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, kind| find(data, kind))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().unwrap(),
+    ))
+}
+
+/// The duration from the movie header, which has 64 bit times in version 1
+fn movie_duration(mvhd: &[u8]) -> Option<f64> {
+    let (timescale, duration) = match mvhd.first()? {
+        1 => (u32_at(mvhd, 20)?, u64_at(mvhd, 24)?),
+        _ => (u32_at(mvhd, 12)?, u32_at(mvhd, 16)? as u64),
+    };
+    // An unknown duration is all ones
+    if timescale == 0 || duration == 0 || duration == u64::MAX || duration == u32::MAX as u64 {
+        return None;
+    }
+    Some(duration as f64 / timescale as f64)
+}
+
+/// The display size from the track header, swapped if the matrix rotates the video by 90 or
+/// 270 degrees, as phones record portrait videos as rotated landscape frames
+fn track_size(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let matrix = match tkhd.first()? {
+        1 => 52,
+        _ => 40,
+    };
+    let a = u32_at(tkhd, matrix)?;
+    let d = u32_at(tkhd, matrix + 16)?;
+    // Fixed point 16.16
+    let width = u32_at(tkhd, matrix + 36)? >> 16;
+    let height = u32_at(tkhd, matrix + 40)? >> 16;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    if a == 0 && d == 0 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// The coded size from the first visual sample entry
+fn sample_entry_size(stsd: &[u8]) -> Option<(u32, u32)> {
+    let width = u16::from_be_bytes(stsd.get(40..42)?.try_into().unwrap()) as u32;
+    let height = u16::from_be_bytes(stsd.get(42..44)?.try_into().unwrap()) as u32;
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// iTunes style cover art, within `udta/meta/ilst/covr/data`
+fn cover_art(moov: &[u8]) -> Option<&[u8]> {
+    let meta = find_path(moov, &[b"udta", b"meta"])?;
+    // The meta box has a version and flags in MP4 but not in QuickTime
+    let meta = match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..)?,
+    };
+    let data = find_path(meta, &[b"ilst", b"covr", b"data"])?;
+    // Type indicator and locale
+    data.get(8..).filter(|image| !image.is_empty())
+}
@@ -0,0 +1,543 @@
+//! Parses the content of posts in the Neue Post Format, which the Tumblr API v2 returns when
+//! requested with `npf=true`, see https://www.tumblr.com/docs/npf
+
+use crate::api_v2::{find_media_files, media_search_prefix, resolve_post_media};
+use crate::blog_dir::BlogDir;
+use crate::model::{Answer, Image, Media, MediaKind, PostType, Text, Video};
+use crate::utils::escape_html;
+use itertools::Itertools;
+use serde::Deserialize;
+use std::cell::RefCell;
+
+/// The content of a post, reblogged posts also contain the content of each earlier post
+#[derive(Deserialize)]
+pub struct NpfContent {
+    blog_name: Option<String>,
+    #[serde(default)]
+    content: Vec<Block>,
+    #[serde(default)]
+    layout: Vec<Layout>,
+    #[serde(default)]
+    trail: Vec<TrailItem>,
+}
+
+#[derive(Deserialize)]
+struct TrailItem {
+    blog: Option<BlogRef>,
+    broken_blog_name: Option<String>,
+    #[serde(default)]
+    content: Vec<Block>,
+    #[serde(default)]
+    layout: Vec<Layout>,
+}
+
+#[derive(Deserialize)]
+struct BlogRef {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Block {
+    Text {
+        #[serde(default)]
+        text: String,
+        subtype: Option<String>,
+        #[serde(default)]
+        formatting: Vec<Formatting>,
+    },
+    Image {
+        #[serde(default)]
+        media: Vec<MediaObject>,
+        alt_text: Option<String>,
+        caption: Option<String>,
+    },
+    Link {
+        url: String,
+        title: Option<String>,
+        description: Option<String>,
+        site_name: Option<String>,
+    },
+    Audio {
+        url: Option<String>,
+        media: Option<OneOrMany>,
+        title: Option<String>,
+        artist: Option<String>,
+        embed_html: Option<String>,
+    },
+    Video {
+        url: Option<String>,
+        media: Option<OneOrMany>,
+        embed_html: Option<String>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+struct MediaObject {
+    url: String,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Audio and video media is documented as a single object but some clients save a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(MediaObject),
+    Many(Vec<MediaObject>),
+}
+
+impl OneOrMany {
+    fn urls(&self) -> Vec<&str> {
+        match self {
+            OneOrMany::One(media) => vec![media.url.as_str()],
+            OneOrMany::Many(media) => media.iter().map(|m| m.url.as_str()).collect(),
+        }
+    }
+}
+
+/// Inline formatting of a text block, `start` and `end` are character offsets into the text
+#[derive(Deserialize)]
+struct Formatting {
+    start: usize,
+    end: usize,
+    #[serde(flatten)]
+    style: Style,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Style {
+    Bold,
+    Italic,
+    Strikethrough,
+    Small,
+    Link {
+        url: String,
+    },
+    Mention {
+        blog: BlogRef,
+    },
+    Color {
+        hex: String,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Layout {
+    /// The blocks containing the question of an answer post
+    Ask {
+        #[serde(default)]
+        blocks: Vec<usize>,
+        attribution: Option<Attribution>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Attribution {
+    blog: Option<BlogRef>,
+}
+
+/// The content of the post or one of the posts in its reblog trail
+struct Section<'a> {
+    blog: Option<&'a str>,
+    content: &'a [Block],
+    layout: &'a [Layout],
+}
+
+impl NpfContent {
+    /// Map the blocks onto the closest post type, photo posts start with an image block and
+    /// contain only images, video posts contain only a video hosted by Tumblr, and the media of
+    /// other posts is listed separately from their body
+    pub fn into_post_type(self, id: u64, media: &BlogDir) -> PostType {
+        let mut sections = self
+            .trail
+            .iter()
+            .map(|item| Section {
+                blog: item
+                    .blog
+                    .as_ref()
+                    .map(|blog| blog.name.as_str())
+                    .or(item.broken_blog_name.as_deref()),
+                content: &item.content,
+                layout: &item.layout,
+            })
+            .collect::<Vec<_>>();
+        if !self.content.is_empty() {
+            sections.push(Section {
+                blog: self.blog_name.as_deref(),
+                content: &self.content,
+                layout: &self.layout,
+            });
+        }
+        let renderer = Renderer {
+            id,
+            media,
+            attribute: sections.len() > 1,
+            rendered_media: RefCell::default(),
+        };
+        let blocks = sections
+            .iter()
+            .flat_map(|section| section.content)
+            .collect::<Vec<_>>();
+
+        let is_ask = sections.iter().any(|section| {
+            section
+                .layout
+                .iter()
+                .any(|layout| matches!(layout, Layout::Ask { .. }))
+        });
+        if is_ask {
+            let body = renderer.render(&sections);
+            return PostType::Answer(Answer {
+                body: Some(body),
+                media: renderer.rendered_media.take(),
+            });
+        }
+
+        let body = renderer.render(&sections);
+        let media = renderer.rendered_media.take();
+
+        let starts_with_image = matches!(blocks.first(), Some(Block::Image { .. }));
+        if starts_with_image && media.iter().all(|media| media.kind == MediaKind::Image) {
+            return PostType::Image(Image {
+                photos: media,
+                caption: Some(body).filter(|caption| !caption.is_empty()),
+            });
+        }
+
+        if let [video] = media.as_slice() {
+            if video.kind == MediaKind::Video {
+                return PostType::Video(Video {
+                    video: media.into_iter().next().map(Box::new),
+                    caption: Some(body),
+                });
+            }
+        }
+
+        // A leading heading is shown as the title, as in the legacy format, so it's dropped
+        // from the body, where it's always the first block rendered
+        let (title, body) = match blocks.first() {
+            Some(
+                heading @ Block::Text {
+                    text,
+                    subtype: Some(subtype),
+                    ..
+                },
+            ) if subtype == "heading1" && !renderer.attribute => {
+                let heading_html = renderer.render_block(heading);
+                let rest = body.strip_prefix(&heading_html).unwrap_or(&body);
+                (
+                    Some(text.clone()),
+                    rest.trim_start_matches('\n').to_string(),
+                )
+            }
+            _ => (None, body),
+        };
+        PostType::Text(Text { title, body, media })
+    }
+}
+
+struct Renderer<'a> {
+    id: u64,
+    media: &'a BlogDir,
+    /// Whether each section is attributed to the blog that posted it
+    attribute: bool,
+    /// The media of the blocks rendered so far, which isn't included in the HTML
+    rendered_media: RefCell<Vec<Media>>,
+}
+
+impl Renderer<'_> {
+    /// Render the blocks of all sections as HTML, except those that are shown separately
+    fn render(&self, sections: &[Section]) -> String {
+        let mut parts = Vec::new();
+        for section in sections {
+            let html = self.render_section(section);
+            if html.is_empty() {
+                continue;
+            }
+            match section.blog {
+                Some(blog) if self.attribute => parts.push(format!(
+                    "<p><a href=\"https://{0}.tumblr.com\">{0}</a>:</p>\n<blockquote>\n{1}\n</blockquote>",
+                    escape_html(blog),
+                    html
+                )),
+                _ => parts.push(html),
+            }
+        }
+        parts.join("\n")
+    }
+
+    fn render_section(&self, section: &Section) -> String {
+        let mut parts = Vec::new();
+        let mut ask_blocks = Vec::new();
+        for layout in section.layout {
+            if let Layout::Ask {
+                blocks,
+                attribution,
+            } = layout
+            {
+                let asker = attribution
+                    .as_ref()
+                    .and_then(|a| a.blog.as_ref())
+                    .map_or("Anonymous", |blog| blog.name.as_str());
+                let question =
+                    self.render_blocks(blocks.iter().filter_map(|idx| section.content.get(*idx)));
+                parts.push(format!(
+                    "<blockquote class=\"ask\">\n<p><em>{} asked:</em></p>\n{}\n</blockquote>",
+                    escape_html(asker),
+                    question
+                ));
+                ask_blocks.extend(blocks);
+            }
+        }
+        let rest = section
+            .content
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !ask_blocks.contains(&idx))
+            .map(|(_, block)| block);
+        parts.push(self.render_blocks(rest));
+        parts.retain(|part| !part.is_empty());
+        parts.join("\n")
+    }
+
+    /// Render a sequence of blocks, grouping consecutive list items into a list
+    fn render_blocks<'b>(&self, blocks: impl Iterator<Item = &'b Block>) -> String {
+        let mut html = Vec::new();
+        let mut list: Option<&str> = None;
+        for block in blocks {
+            let list_tag = match block {
+                Block::Text {
+                    subtype: Some(subtype),
+                    ..
+                } => match subtype.as_str() {
+                    "ordered-list-item" => Some("ol"),
+                    "unordered-list-item" => Some("ul"),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if list != list_tag {
+                if let Some(tag) = list {
+                    html.push(format!("</{}>", tag));
+                }
+                if let Some(tag) = list_tag {
+                    html.push(format!("<{}>", tag));
+                }
+                list = list_tag;
+            }
+            html.push(self.render_block(block));
+        }
+        if let Some(tag) = list {
+            html.push(format!("</{}>", tag));
+        }
+        html.retain(|part| !part.is_empty());
+        html.join("\n")
+    }
+
+    fn render_block(&self, block: &Block) -> String {
+        match block {
+            Block::Text {
+                text,
+                subtype,
+                formatting,
+            } => {
+                let html = format_text(text, formatting);
+                let (open, close) = match subtype.as_deref() {
+                    Some("heading1") => ("<h1>", "</h1>"),
+                    Some("heading2") => ("<h2>", "</h2>"),
+                    Some("quote") | Some("indented") => ("<blockquote>", "</blockquote>"),
+                    Some("chat") => ("<p class=\"chat\">", "</p>"),
+                    Some("quirky") => ("<p class=\"quirky\">", "</p>"),
+                    Some("ordered-list-item") | Some("unordered-list-item") => ("<li>", "</li>"),
+                    _ => ("<p>", "</p>"),
+                };
+                format!("{}{}{}", open, html, close)
+            }
+            Block::Image {
+                media: images,
+                alt_text,
+                caption,
+            } => {
+                let image = self.image_media(images, alt_text);
+                self.rendered_media.borrow_mut().push(image);
+                caption
+                    .as_ref()
+                    .map(|caption| format!("<p>{}</p>", escape_html(caption)))
+                    .unwrap_or_default()
+            }
+            Block::Link {
+                url,
+                title,
+                description,
+                site_name,
+            } => {
+                let mut html = format!(
+                    "<p><a href=\"{}\">{}</a></p>",
+                    escape_html(url),
+                    escape_html(title.as_deref().unwrap_or(url))
+                );
+                for text in [description, site_name].into_iter().flatten() {
+                    html += &format!("\n<p>{}</p>", escape_html(text));
+                }
+                html
+            }
+            Block::Audio {
+                url,
+                media,
+                title,
+                artist,
+                embed_html,
+            } => {
+                let name = [artist, title].into_iter().flatten().join(" - ");
+                let name = (!name.is_empty()).then(|| format!("<p>{}</p>", escape_html(&name)));
+                let player = self.player(MediaKind::Audio, url, media, embed_html);
+                name.into_iter()
+                    .chain(Some(player).filter(|player| !player.is_empty()))
+                    .join("\n")
+            }
+            Block::Video {
+                url,
+                media,
+                embed_html,
+            } => self.player(MediaKind::Video, url, media, embed_html),
+            Block::Unsupported => String::new(),
+        }
+    }
+
+    /// Media hosted by Tumblr is listed with the file on disk matching it, other media is
+    /// embedded, as is hosted media that couldn't be found, and listed as missing
+    fn player(
+        &self,
+        kind: MediaKind,
+        url: &Option<String>,
+        media: &Option<OneOrMany>,
+        embed_html: &Option<String>,
+    ) -> String {
+        if let Some(hosted) = media {
+            let urls = hosted.urls();
+            let found = resolve_post_media(&urls, self.id, self.media);
+            let hosted = Media::from_matches(
+                kind,
+                urls.first().copied(),
+                urls.first()
+                    .and_then(|url| media_search_prefix(url))
+                    .as_deref(),
+                found.into_iter().collect(),
+            );
+            let is_missing = hosted.is_missing();
+            self.rendered_media.borrow_mut().push(hosted);
+            if !is_missing {
+                return String::new();
+            }
+        } else if embed_html.is_some() || url.is_some() {
+            // Media from other sites isn't downloaded, it's listed so that it's reported missing
+            self.rendered_media.borrow_mut().push(Media::missing(
+                kind,
+                url.as_deref(),
+                String::from("Not hosted by Tumblr, so it wasn't downloaded"),
+            ));
+        }
+        if let Some(embed_html) = embed_html {
+            embed_html.clone()
+        } else if let Some(url) = url {
+            format!("<p><a href=\"{0}\">{0}</a></p>", escape_html(url))
+        } else {
+            String::new()
+        }
+    }
+
+    /// An image with the files on disk matching it, the original size is listed first
+    fn image_media(&self, images: &[MediaObject], alt_text: &Option<String>) -> Media {
+        let original = images.first();
+        Media {
+            width: original.and_then(|image| image.width),
+            height: original.and_then(|image| image.height),
+            alt_text: alt_text.clone(),
+            ..Media::from_matches(
+                MediaKind::Image,
+                original.map(|image| image.url.as_str()),
+                original
+                    .and_then(|image| media_search_prefix(&image.url))
+                    .as_deref(),
+                self.resolve_images(images),
+            )
+        }
+    }
+
+    /// All files on disk matching an image, best first
+    fn resolve_images(&self, images: &[MediaObject]) -> Vec<String> {
+        let urls = images.iter().map(|m| m.url.as_str()).collect::<Vec<_>>();
+        let files = find_media_files(&urls, self.media);
+        if files.is_empty() {
+            log::warn!(
+                "Unable to find file matching {} for post {}",
+                urls.first().unwrap_or(&""),
+                self.id
+            );
+        }
+        files
+    }
+}
+
+/// Render text with its inline formatting as HTML, formatting ranges may overlap so the text
+/// is split wherever a range starts or ends and each part is wrapped in its own tags
+fn format_text(text: &str, formatting: &[Formatting]) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let bounds = formatting
+        .iter()
+        .flat_map(|f| [f.start, f.end])
+        .chain([0, chars.len()])
+        .map(|idx| idx.min(chars.len()))
+        .sorted()
+        .dedup();
+    let mut html = String::new();
+    for (start, end) in bounds.tuple_windows() {
+        let styles = formatting
+            .iter()
+            .filter(|f| f.start <= start && f.end >= end)
+            .map(|f| f.style.tags())
+            .collect::<Vec<_>>();
+        for (open, _) in &styles {
+            html += open;
+        }
+        let part = chars[start..end].iter().collect::<String>();
+        html += &escape_html(&part).replace('\n', "<br>");
+        for (_, close) in styles.iter().rev() {
+            html += close;
+        }
+    }
+    html
+}
+
+impl Style {
+    fn tags(&self) -> (String, &'static str) {
+        match self {
+            Style::Bold => ("<b>".to_string(), "</b>"),
+            Style::Italic => ("<i>".to_string(), "</i>"),
+            Style::Strikethrough => ("<s>".to_string(), "</s>"),
+            Style::Small => ("<small>".to_string(), "</small>"),
+            Style::Link { url } => (format!("<a href=\"{}\">", escape_html(url)), "</a>"),
+            Style::Mention { blog } => (
+                format!(
+                    "<a href=\"https://{}.tumblr.com\">",
+                    escape_html(&blog.name)
+                ),
+                "</a>",
+            ),
+            Style::Color { hex } => (
+                format!("<span style=\"color: {}\">", escape_html(hex)),
+                "</span>",
+            ),
+            Style::Unsupported => (String::new(), ""),
+        }
+    }
+}
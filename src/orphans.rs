@@ -0,0 +1,137 @@
+//! Finds the media files within a blog that aren't referenced by any post, such as the files of
+//! posts that failed to parse or were deleted, and duplicate downloads
+//! Files are only quarantined when every post could be read, so that the files of posts that
+//! failed to parse aren't moved
+
+use crate::blog::Blog;
+use crate::model::{MediaKind, Post};
+use crate::sniff;
+use crate::utils::RebaseFileUrls;
+use crate::{find_blog, Args};
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+
+/// The folder orphaned files are moved into, which is hidden so that its files aren't indexed
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OrphansArgs {
+    /// Name of the blog to check
+    blog: String,
+    /// Move the orphaned files into a `.quarantine` folder within the blog, keeping their paths
+    #[arg(long)]
+    quarantine: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrphanReport {
+    pub files: Vec<OrphanFile>,
+    /// Total size of the orphaned files in bytes
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrphanFile {
+    pub path: String,
+    pub size: u64,
+}
+
+impl OrphanReport {
+    /// The media files within the blog that aren't referenced by any of its posts, either as
+    /// media, an alternative copy of media, or within a post body
+    pub fn new(blog: &Blog, posts: &[Post]) -> anyhow::Result<Self> {
+        let rebase = RebaseFileUrls::new(blog, "");
+        let mut referenced = HashSet::new();
+        for post in posts {
+            referenced.extend(rebase.post_files(post));
+            for media in post.media() {
+                referenced.extend(
+                    media
+                        .alternatives
+                        .iter()
+                        .filter_map(|url| rebase.file_path(url)),
+                );
+            }
+        }
+
+        let mut files = Vec::new();
+        for file in blog.blog_dir()?.files {
+            if referenced.contains(&file) {
+                continue;
+            }
+            // Metadata and other files that posts don't reference, by their contents as media may
+            // be saved without an extension or with the wrong one
+            let mime_type = sniff::file_mime_type(blog, &file);
+            if mime_type
+                .as_deref()
+                .and_then(MediaKind::from_mime_type)
+                .is_none()
+            {
+                continue;
+            }
+            let size = blog.file_size(&file)?.unwrap_or_default();
+            files.push(OrphanFile { path: file, size });
+        }
+        Ok(Self {
+            bytes: files.iter().map(|file| file.size).sum(),
+            files,
+        })
+    }
+
+    /// Move the orphaned files into the quarantine folder, nothing is moved if any of the files
+    /// already exists there
+    pub fn quarantine(&self, blog: &Blog) -> anyhow::Result<()> {
+        if blog.is_archive() {
+            bail!("Files can't be moved out of an archive");
+        }
+        let quarantine = blog.path.join(QUARANTINE_DIR);
+        if let Some(existing) = self
+            .files
+            .iter()
+            .map(|file| quarantine.join(&file.path))
+            .find(|destination| destination.exists())
+        {
+            bail!("{} already exists", existing.display());
+        }
+        for file in &self.files {
+            let destination = quarantine.join(&file.path);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(blog.path.join(&file.path), &destination)
+                .with_context(|| format!("Unable to move {}", file.path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the orphaned files of a blog, moving them into the quarantine folder if requested
+pub fn print_orphans(args: &Args, orphans_args: &OrphansArgs) -> anyhow::Result<()> {
+    let blog = find_blog(args, &orphans_args.blog)?;
+    let posts = if orphans_args.quarantine {
+        blog.load_strict()
+            .context("Not quarantining, as the files of posts that can't be read would be moved")?
+    } else {
+        blog.load()?
+    };
+    let report = OrphanReport::new(&blog, &posts)?;
+    for file in &report.files {
+        println!("{}\t{}", file.size, file.path);
+    }
+    println!(
+        "{} orphaned files, {} bytes",
+        report.files.len(),
+        report.bytes
+    );
+    if orphans_args.quarantine && !report.files.is_empty() {
+        report.quarantine(&blog)?;
+        println!(
+            "Moved {} files to {}",
+            report.files.len(),
+            blog.path.join(QUARANTINE_DIR).display()
+        );
+    }
+    Ok(())
+}
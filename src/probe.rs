@@ -0,0 +1,182 @@
+//! Reads the dimensions of images from their headers, without decoding them, so that clients
+//! can lay out posts before the images have loaded
+
+use std::io::{self, Read};
+
+/// The dimensions of an image, and the number of frames of a GIF
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Option<u32>,
+}
+
+/// Read the dimensions of an image of a detected MIME type, returns `None` if it isn't a
+/// supported type or the header is invalid
+/// Only as much of the file as is needed is read, except for GIFs which are read to the end to
+/// count their frames
+pub fn probe(mime_type: &str, reader: impl Read) -> io::Result<Option<ImageInfo>> {
+    let mut reader = io::BufReader::new(reader);
+    let result = match mime_type {
+        "image/png" => probe_png(&mut reader),
+        "image/gif" => probe_gif(&mut reader),
+        "image/jpeg" => probe_jpeg(&mut reader),
+        "image/webp" => probe_webp(&mut reader),
+        _ => return Ok(None),
+    };
+    match result {
+        Ok(info) => Ok(info),
+        // A truncated header
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// The size is in the `IHDR` chunk, which must come first
+fn probe_png(reader: &mut impl Read) -> io::Result<Option<ImageInfo>> {
+    let header = read_array::<24>(reader)?;
+    if &header[12..16] != b"IHDR" {
+        return Ok(None);
+    }
+    Ok(Some(ImageInfo {
+        width: u32::from_be_bytes(header[16..20].try_into().unwrap()),
+        height: u32::from_be_bytes(header[20..24].try_into().unwrap()),
+        frames: None,
+    }))
+}
+
+/// The size is in the logical screen descriptor, frames are counted by their image
+/// descriptors, skipping over the compressed image data
+fn probe_gif(reader: &mut impl Read) -> io::Result<Option<ImageInfo>> {
+    let header = read_array::<13>(reader)?;
+    let width = u16::from_le_bytes([header[6], header[7]]) as u32;
+    let height = u16::from_le_bytes([header[8], header[9]]) as u32;
+    skip_color_table(reader, header[10])?;
+
+    let mut frames = 0;
+    loop {
+        let mut block = [0; 1];
+        if reader.read(&mut block)? == 0 {
+            // Truncated, count the frames so far
+            break;
+        }
+        match block[0] {
+            // Extension
+            0x21 => {
+                read_array::<1>(reader)?;
+                skip_sub_blocks(reader)?;
+            }
+            // Image descriptor
+            0x2C => {
+                frames += 1;
+                let descriptor = read_array::<9>(reader)?;
+                skip_color_table(reader, descriptor[8])?;
+                // LZW minimum code size
+                read_array::<1>(reader)?;
+                skip_sub_blocks(reader)?;
+            }
+            // Trailer, or an invalid block
+            _ => break,
+        }
+    }
+    Ok(Some(ImageInfo {
+        width,
+        height,
+        frames: Some(frames),
+    }))
+}
+
+/// Skip the global or local color table, if the flags say there is one
+fn skip_color_table(reader: &mut impl Read, flags: u8) -> io::Result<()> {
+    if flags & 0x80 != 0 {
+        skip(reader, 3 << ((flags & 0x07) + 1))?;
+    }
+    Ok(())
+}
+
+fn skip_sub_blocks(reader: &mut impl Read) -> io::Result<()> {
+    loop {
+        let [len] = read_array::<1>(reader)?;
+        if len == 0 {
+            return Ok(());
+        }
+        skip(reader, len as u64)?;
+    }
+}
+
+/// The size is in the start of frame segment, which may follow metadata segments such as EXIF
+fn probe_jpeg(reader: &mut impl Read) -> io::Result<Option<ImageInfo>> {
+    if read_array::<2>(reader)? != [0xFF, 0xD8] {
+        return Ok(None);
+    }
+    loop {
+        let [prefix, mut marker] = read_array::<2>(reader)?;
+        if prefix != 0xFF {
+            return Ok(None);
+        }
+        // Markers may be padded with any number of fill bytes
+        while marker == 0xFF {
+            [marker] = read_array::<1>(reader)?;
+        }
+        match marker {
+            // Markers without a segment
+            0x01 | 0xD0..=0xD7 => continue,
+            // The image data or end of the image, without a start of frame
+            0xD9 | 0xDA => return Ok(None),
+            _ => {}
+        }
+        let len = u16::from_be_bytes(read_array::<2>(reader)?) as u64;
+        let is_start_of_frame =
+            matches!(marker, 0xC0..=0xCF) && ![0xC4, 0xC8, 0xCC].contains(&marker);
+        if is_start_of_frame {
+            let frame = read_array::<5>(reader)?;
+            return Ok(Some(ImageInfo {
+                width: u16::from_be_bytes([frame[3], frame[4]]) as u32,
+                height: u16::from_be_bytes([frame[1], frame[2]]) as u32,
+                frames: None,
+            }));
+        }
+        skip(reader, len.saturating_sub(2))?;
+    }
+}
+
+/// The size is in the first chunk, which depends on whether the image is lossy, lossless or
+/// uses extended features such as animation
+fn probe_webp(reader: &mut impl Read) -> io::Result<Option<ImageInfo>> {
+    let header = read_array::<30>(reader)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WEBP" {
+        return Ok(None);
+    }
+    let u24 = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    let (width, height) = match &header[12..16] {
+        b"VP8 " => (
+            (u16::from_le_bytes([header[26], header[27]]) & 0x3FFF) as u32,
+            (u16::from_le_bytes([header[28], header[29]]) & 0x3FFF) as u32,
+        ),
+        b"VP8L" => {
+            let bits = u32::from_le_bytes(header[21..25].try_into().unwrap());
+            ((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1)
+        }
+        b"VP8X" => (u24(&header[24..27]) + 1, u24(&header[27..30]) + 1),
+        _ => return Ok(None),
+    };
+    Ok(Some(ImageInfo {
+        width,
+        height,
+        frames: None,
+    }))
+}
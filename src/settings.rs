@@ -0,0 +1,95 @@
+//! Reads the settings TumblThree keeps for each blog in the `Index` folder, written as JSON to
+//! `Index/<blog>.<blog type>`, alongside `Index/<blog>_files.<blog type>` which lists the
+//! downloaded files
+
+use chrono::{DateTime, NaiveDateTime};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub const INDEX_DIR: &str = "Index";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct BlogSettings {
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// Number of posts on the blog as of the last crawl
+    pub posts: u64,
+    /// Number of posts of each type on the blog as of the last crawl
+    pub photos: u64,
+    pub videos: u64,
+    pub texts: u64,
+    pub answers: u64,
+    /// Number of items found to download
+    pub total_count: u64,
+    /// Number of items downloaded, of every type despite the name
+    pub downloaded_images: u64,
+    #[serde(deserialize_with = "deserialize_date")]
+    pub last_complete_crawl: Option<NaiveDateTime>,
+    pub online: bool,
+}
+
+/// Read the settings of every blog in the Index folder, by blog name
+/// Missing or unreadable settings files are logged and skipped, as they are only informative
+pub fn read_index(base_dir: &Path) -> HashMap<String, BlogSettings> {
+    let Ok(entries) = fs::read_dir(base_dir.join(INDEX_DIR)) else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|entry| !entry.file_name().to_string_lossy().contains("_files."))
+        .filter_map(|entry| {
+            let path = entry.path();
+            let settings = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| {
+                    let text = text.trim_start_matches('\u{feff}');
+                    Ok(serde_json::from_str::<BlogSettings>(text)?)
+                });
+            match settings {
+                Ok(settings) if !settings.name.is_empty() => {
+                    Some((settings.name.clone(), settings))
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    log::warn!("Unable to read blog settings {}: {:#}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Parse a .NET date, either ISO 8601 or the `/Date(1588605634000+0200)/` format, in UTC
+/// The default `0001-01-01T00:00:00` date is used for blogs that were never crawled
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    static DOTNET_DATE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^/Date\((-?\d+)([+-]\d{4})?\)/$").unwrap());
+
+    let Some(date) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let parsed = if let Some(captures) = DOTNET_DATE.captures(&date) {
+        captures[1]
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|date| date.naive_utc())
+    } else {
+        DateTime::parse_from_rfc3339(&date)
+            .map(|date| date.naive_utc())
+            .or_else(|_| date.parse::<NaiveDateTime>())
+            .ok()
+    };
+    Ok(parsed.filter(|date| date.and_utc().timestamp() > 0))
+}
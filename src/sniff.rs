@@ -0,0 +1,208 @@
+//! Detects the type of media files from their contents, as TumblThree may save files with the
+//! wrong extension (https://github.com/TumblThreeApp/TumblThree/issues/439)
+
+use crate::blog::Blog;
+use crate::model::{MediaKind, Post};
+use crate::mp4::{self, VideoInfo};
+use crate::probe::{self, ImageInfo};
+use crate::thumbnail;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Number of bytes needed to detect any of the supported types
+pub const HEADER_LEN: usize = 16;
+
+/// The MIME type of a file from its first bytes, if it is a supported media type
+pub fn sniff(header: &[u8]) -> Option<&'static str> {
+    let starts_with = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+    if starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if starts_with(b"GIF87a") || starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if starts_with(b"RIFF") && at(8, b"WEBP") {
+        Some("image/webp")
+    } else if at(4, b"ftyp") {
+        // ISO base media files are told apart by their major brand, HEIF and AVIF images use
+        // the same container as MP4 videos
+        match header.get(8..12)? {
+            b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1"
+            | b"M4V " | b"M4VH" | b"M4VP" | b"dash" | b"mmp4" | b"MSNV" => Some("video/mp4"),
+            b"qt  " => Some("video/quicktime"),
+            b"3gp4" | b"3gp5" | b"3gp6" => Some("video/3gpp"),
+            b"M4A " | b"M4B " => Some("audio/mp4"),
+            b"heic" | b"heix" => Some("image/heic"),
+            b"mif1" | b"msf1" | b"heif" => Some("image/heif"),
+            b"avif" | b"avis" => Some("image/avif"),
+            _ => None,
+        }
+    } else if starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some("video/webm")
+    } else if starts_with(b"ID3") || is_mpeg_audio_frame(header) {
+        Some("audio/mpeg")
+    } else {
+        None
+    }
+}
+
+/// An MPEG audio frame header, with the sync bits set and a layer other than the reserved
+/// value used by AAC
+fn is_mpeg_audio_frame(header: &[u8]) -> bool {
+    match header {
+        [0xFF, second, ..] => second & 0xE0 == 0xE0 && (second >> 1) & 0x3 != 0,
+        _ => false,
+    }
+}
+
+/// The MIME type of a file within a blog from its contents, falling back to its extension,
+/// returns `None` if there is no such file
+pub fn file_mime_type(blog: &Blog, file: &str) -> Option<String> {
+    let header = match blog.read_range(file, 0, HEADER_LEN) {
+        Ok(Some(header)) => header,
+        Ok(None) => return None,
+        Err(e) => {
+            log::warn!("Unable to read {}: {:#}", file, e);
+            vec![]
+        }
+    };
+    match sniff(&header) {
+        Some(mime_type) => Some(mime_type.to_owned()),
+        None => {
+            let (_, ext) = file.rsplit_once('.')?;
+            Some(
+                actix_files::file_extension_to_mime(ext)
+                    .essence_str()
+                    .to_owned(),
+            )
+        }
+    }
+}
+
+/// What is known about a media file from its contents
+struct FileDescription {
+    mime_type: Option<String>,
+    size: Option<u64>,
+    image: Option<ImageInfo>,
+    video: Option<VideoInfo>,
+}
+
+impl FileDescription {
+    fn new(blog: &Blog, file: &str) -> Self {
+        let size = blog.file_size(file).unwrap_or_else(|e| {
+            log::warn!("Unable to read {}: {:#}", file, e);
+            None
+        });
+        let mime_type = file_mime_type(blog, file);
+        let image = mime_type
+            .as_deref()
+            .filter(|mime_type| mime_type.starts_with("image/"))
+            .and_then(|mime_type| {
+                let (reader, _) = blog.open_file(file).ok()??;
+                probe::probe(mime_type, reader)
+                    .inspect_err(|e| log::warn!("Unable to read {}: {:#}", file, e))
+                    .ok()?
+            });
+        let video = mime_type
+            .as_deref()
+            .filter(|mime_type| mp4::SUPPORTED_TYPES.contains(mime_type))
+            .and_then(|_| {
+                mp4::probe(blog, file)
+                    .inspect_err(|e| log::warn!("Unable to read {}: {:#}", file, e))
+                    .ok()?
+            });
+        Self {
+            mime_type,
+            size,
+            image,
+            video,
+        }
+    }
+}
+
+/// Number of file descriptions kept, beyond which they are all forgotten
+const MAX_DESCRIPTIONS: usize = 100_000;
+
+/// File descriptions by the blog path and the path within the blog, along with the
+/// modification time and size of the file when it was described
+type DescriptionCache = HashMap<(PathBuf, String), (SystemTime, u64, Arc<FileDescription>)>;
+
+/// Describe a file within a blog, descriptions are kept until the file is modified as
+/// describing a file requires reading it, and videos' metadata may be at the end of the file
+fn describe_file(blog: &Blog, file: &str) -> Arc<FileDescription> {
+    static DESCRIPTIONS: Lazy<Mutex<DescriptionCache>> = Lazy::new(Default::default);
+
+    let (Ok(Some(modified)), Ok(Some(size))) = (blog.modified(file), blog.file_size(file)) else {
+        return Arc::new(FileDescription::new(blog, file));
+    };
+    let key = (blog.path.clone(), file.to_owned());
+    if let Some((cached_modified, cached_size, description)) =
+        DESCRIPTIONS.lock().unwrap().get(&key)
+    {
+        if *cached_modified == modified && *cached_size == size {
+            return description.clone();
+        }
+    }
+    let description = Arc::new(FileDescription::new(blog, file));
+    let mut descriptions = DESCRIPTIONS.lock().unwrap();
+    if descriptions.len() >= MAX_DESCRIPTIONS {
+        descriptions.clear();
+    }
+    descriptions.insert(key, (modified, size, description.clone()));
+    description
+}
+
+/// Describe the file of every media found for the posts: its path within the blog, its type
+/// detected from its contents, its size, its dimensions if it is an image, its thumbnails, and
+/// its duration, codec and poster if it is a video, animations saved as videos are listed as
+/// videos
+/// The files are those found in the blog's file index, each is only read once until it is
+/// modified, only the media of posts that are shown should be described
+pub fn describe_media(blog: &Blog, posts: &mut [Post]) {
+    let prefix = blog.file_url("");
+    let mut described = HashMap::<String, Arc<FileDescription>>::new();
+    for post in posts {
+        for media in post.media_mut() {
+            let Some(path) = media
+                .url
+                .as_deref()
+                .and_then(|url| url.strip_prefix(&prefix))
+            else {
+                continue;
+            };
+            let description = described
+                .entry(path.to_owned())
+                .or_insert_with(|| describe_file(blog, path));
+            let mime_type = description.mime_type.as_deref();
+            if let Some(kind) = mime_type.and_then(MediaKind::from_mime_type) {
+                media.kind = kind;
+            }
+            if let Some(mime_type) = mime_type {
+                media.thumbnails = thumbnail::thumbnails(blog, path, mime_type);
+            }
+            // The file may be a different size than the one the source describes
+            if let Some(image) = description.image {
+                media.width = Some(image.width);
+                media.height = Some(image.height);
+                media.frames = image.frames;
+            }
+            if let Some(video) = &description.video {
+                media.width = video.width.or(media.width);
+                media.height = video.height.or(media.height);
+                media.duration = video.duration;
+                media.codec = video.codec.clone();
+                media.has_audio = Some(video.has_audio);
+                media.poster = video
+                    .has_cover
+                    .then(|| format!("{}/{}", blog.poster_url, path));
+            }
+            media.mime_type = description.mime_type.clone();
+            media.size = description.size;
+            media.path = Some(path.to_owned());
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::blog::Blog;
+use crate::model::Post;
+use crate::utils::RebaseFileUrls;
+use crate::{find_blog, sniff, Args};
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use itertools::Itertools;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum Generator {
+    Hugo,
+    Jekyll,
+    Zola,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum ContentFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SiteArgs {
+    /// Name of the blog to export
+    blog: String,
+    /// Static site generator to write content for
+    #[arg(long, value_enum, default_value_t = Generator::Hugo)]
+    generator: Generator,
+    /// Format of the post content files, Markdown content contains the post HTML unchanged
+    #[arg(long, value_enum, default_value_t = ContentFormat::Markdown)]
+    format: ContentFormat,
+    /// Site directory to write into, defaults to `<blog>-site`
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl Generator {
+    /// Directory posts are written to, relative to the site directory
+    fn content_dir(self) -> &'static str {
+        match self {
+            Generator::Hugo | Generator::Zola => "content/posts",
+            Generator::Jekyll => "_posts",
+        }
+    }
+
+    /// Directory that is served as-is, relative to the site directory
+    fn static_dir(self) -> &'static str {
+        match self {
+            Generator::Hugo | Generator::Zola => "static",
+            Generator::Jekyll => "assets",
+        }
+    }
+
+    /// URL the static directory is served at
+    fn static_url(self) -> &'static str {
+        match self {
+            Generator::Hugo | Generator::Zola => "/",
+            Generator::Jekyll => "/assets/",
+        }
+    }
+}
+
+/// Export a blog as content for a static site generator
+pub fn export(args: &Args, site_args: &SiteArgs) -> anyhow::Result<()> {
+    let generator = site_args.generator;
+    let extension = match (site_args.format, generator) {
+        (ContentFormat::Markdown, _) => "md",
+        (ContentFormat::Html, Generator::Zola) => bail!("Zola only supports Markdown content"),
+        (ContentFormat::Html, _) => "html",
+    };
+    let blog = find_blog(args, &site_args.blog)?;
+    let mut posts = blog.load()?;
+    sniff::describe_media(&blog, &mut posts);
+    let output = site_args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}-site", site_args.blog)));
+
+    let content_dir = output.join(generator.content_dir());
+    let media_dir = output
+        .join(generator.static_dir())
+        .join("media")
+        .join(&site_args.blog);
+    fs::create_dir_all(&content_dir)?;
+    fs::create_dir_all(&media_dir)?;
+    if let Generator::Zola = generator {
+        let index = content_dir.join("_index.md");
+        if !index.exists() {
+            let title = serde_json::to_string(&site_args.blog)?;
+            fs::write(
+                index,
+                format!("+++\ntitle = {}\nsort_by = \"date\"\n+++\n", title),
+            )?;
+        }
+    }
+
+    let media_url = format!("{}media/{}", generator.static_url(), site_args.blog);
+    let rebase = RebaseFileUrls::new(&blog, &media_url);
+    for post in &posts {
+        for file_name in rebase.post_files(post) {
+            if let Err(e) = copy_media(&blog, &file_name, &media_dir.join(&file_name)) {
+                log::warn!("Skipping media file {}: {:#}", file_name, e);
+            }
+        }
+        let html = post.to_html();
+        let mut name = post.common.id.to_string();
+        if let Some(slug) = post.common.slug() {
+            write!(name, "-{}", slug)?;
+        }
+        let path = match (generator, post.common.parsed_date()) {
+            (Generator::Jekyll, Some(date)) => content_dir.join(format!(
+                "{}-{}.{}",
+                date.format("%Y-%m-%d"),
+                name,
+                extension
+            )),
+            (Generator::Jekyll, None) => {
+                // Jekyll requires the date in the filename of published posts
+                log::warn!("Post {} has no date, writing as a draft", post.common.id);
+                let drafts = output.join("_drafts");
+                fs::create_dir_all(&drafts)?;
+                drafts.join(format!("{}.{}", name, extension))
+            }
+            _ => content_dir.join(format!("{}.{}", name, extension)),
+        };
+        let content = format!(
+            "{}\n{}\n",
+            front_matter(post, generator)?,
+            rebase.rebase(&html)
+        );
+        fs::write(&path, content).with_context(|| format!("Unable to write {}", path.display()))?;
+    }
+    log::info!("Exported {} posts to {}", posts.len(), output.display());
+    Ok(())
+}
+
+fn copy_media(blog: &Blog, file_name: &str, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let Some((mut from, from_len)) = blog.open_file(file_name)? else {
+        log::warn!("Skipping missing media file {}", file_name);
+        return Ok(());
+    };
+    if fs::metadata(to)
+        .map(|m| m.len() == from_len)
+        .unwrap_or(false)
+    {
+        return Ok(());
+    }
+    io::copy(&mut from, &mut File::create(to)?)
+        .with_context(|| format!("Unable to copy {}", file_name))?;
+    Ok(())
+}
+
+/// Front matter in the format expected by the generator
+/// The Tumblr post type is written as `tumblr_type` because `type` selects the layout
+/// in Hugo and Jekyll
+fn front_matter(post: &Post, generator: Generator) -> anyhow::Result<String> {
+    let string = |value: &str| serde_json::to_string(value);
+    let title = string(post.title().unwrap_or_default())?;
+    let date = post
+        .common
+        .parsed_date()
+        .map(|d| d.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    let tags = serde_json::to_string(&post.common.tags)?;
+    let aliases = serde_json::to_string(&aliases(post))?;
+    let post_type = string(post.r#type.name())?;
+    let url = post.common.post_url.as_deref().map(string).transpose()?;
+
+    let mut out = String::new();
+    match generator {
+        Generator::Hugo | Generator::Jekyll => {
+            writeln!(out, "---")?;
+            if let Generator::Jekyll = generator {
+                writeln!(out, "layout: post")?;
+            }
+            writeln!(out, "title: {}", title)?;
+            if let Some(date) = date {
+                writeln!(out, "date: {}", date)?;
+            }
+            writeln!(out, "tags: {}", tags)?;
+            match generator {
+                // Requires the jekyll-redirect-from plugin
+                Generator::Jekyll => writeln!(out, "redirect_from: {}", aliases)?,
+                _ => writeln!(out, "aliases: {}", aliases)?,
+            }
+            writeln!(out, "tumblr_id: {}", post.common.id)?;
+            writeln!(out, "tumblr_type: {}", post_type)?;
+            if let Some(url) = url {
+                writeln!(out, "tumblr_url: {}", url)?;
+            }
+            writeln!(out, "---")?;
+        }
+        Generator::Zola => {
+            writeln!(out, "+++")?;
+            writeln!(out, "title = {}", title)?;
+            if let Some(date) = date {
+                writeln!(out, "date = {}", date)?;
+            }
+            writeln!(out, "aliases = {}", aliases)?;
+            writeln!(out, "[taxonomies]")?;
+            writeln!(out, "tags = {}", tags)?;
+            writeln!(out, "[extra]")?;
+            writeln!(out, "tumblr_id = {}", post.common.id)?;
+            writeln!(out, "tumblr_type = {}", post_type)?;
+            if let Some(url) = url {
+                writeln!(out, "tumblr_url = {}", url)?;
+            }
+            writeln!(out, "+++")?;
+        }
+    }
+    Ok(out)
+}
+
+/// Paths to redirect from so that links to the original Tumblr posts keep working
+fn aliases(post: &Post) -> Vec<String> {
+    post.common
+        .url_path()
+        .map(ToOwned::to_owned)
+        .into_iter()
+        .chain([format!("/post/{}", post.common.id)])
+        .unique()
+        .collect()
+}
@@ -0,0 +1,252 @@
+use crate::blog::{list_blogs, BlogSummary};
+use crate::model::Post;
+use crate::settings::BlogSettings;
+use crate::{find_blog, Args};
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+const POST_TYPES: [&str; 4] = ["Image", "Video", "Text", "Answer"];
+/// Number of tags included in the blog statistics
+const TOP_TAGS: usize = 20;
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatsArgs {
+    /// Names of the blogs to show, defaults to all blogs
+    blogs: Vec<String>,
+}
+
+/// How much of a blog has been downloaded, compared to the counts TumblThree recorded when
+/// crawling the blog
+#[derive(Serialize, Debug)]
+pub struct Completeness {
+    /// Number of posts on the blog, if known
+    pub posts_online: Option<u64>,
+    pub posts_present: u64,
+    /// Number of posts with at least one media file that couldn't be found
+    pub posts_missing_media: u64,
+    /// Completeness of each post type
+    pub types: BTreeMap<&'static str, TypeCompleteness>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct TypeCompleteness {
+    pub online: Option<u64>,
+    pub present: u64,
+    pub missing_media: u64,
+}
+
+impl Completeness {
+    pub fn new(posts: &[Post], settings: Option<&BlogSettings>) -> Self {
+        let mut types = POST_TYPES
+            .into_iter()
+            .map(|name| {
+                let online = settings.map(|s| match name {
+                    "Image" => s.photos,
+                    "Video" => s.videos,
+                    "Text" => s.texts,
+                    _ => s.answers,
+                });
+                let completeness = TypeCompleteness {
+                    online,
+                    ..TypeCompleteness::default()
+                };
+                (name, completeness)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut posts_missing_media = 0;
+        for post in posts {
+            let missing_media = post.unresolved_media() > 0;
+            let completeness = types.entry(post.r#type.name()).or_default();
+            completeness.present += 1;
+            if missing_media {
+                completeness.missing_media += 1;
+                posts_missing_media += 1;
+            }
+        }
+        Self {
+            posts_online: settings.map(|s| s.posts),
+            posts_present: posts.len() as u64,
+            posts_missing_media,
+            types,
+        }
+    }
+}
+
+/// Load a blog to compare it with its TumblThree settings
+pub fn blog_completeness(args: &Args, blog: &BlogSummary) -> anyhow::Result<Completeness> {
+    let posts = find_blog(args, &blog.name)?.load()?;
+    Ok(Completeness::new(&posts, blog.settings.as_ref()))
+}
+
+/// Statistics of the posts of a blog
+#[derive(Serialize, Debug)]
+pub struct BlogStats {
+    pub posts: usize,
+    /// Number of posts of each type
+    pub types: BTreeMap<&'static str, usize>,
+    /// Number of posts by year, e.g. `2014`
+    pub years: BTreeMap<String, usize>,
+    /// Number of posts by month, e.g. `2014-03`
+    pub months: BTreeMap<String, usize>,
+    /// Number of posts without a recognised date
+    pub undated: usize,
+    /// The most used tags, most used first
+    pub top_tags: Vec<TagCount>,
+    /// Number of distinct media files referenced by the posts and found within the blog
+    pub media_files: usize,
+    /// Total size of those media files in bytes
+    pub media_bytes: u64,
+    /// Number of media files referenced by the posts that couldn't be found
+    pub unresolved_media: usize,
+    pub first_post: Option<String>,
+    pub last_post: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+impl BlogStats {
+    pub fn new(posts: &[Post]) -> Self {
+        let mut types = POST_TYPES
+            .into_iter()
+            .map(|name| (name, 0))
+            .collect::<BTreeMap<_, _>>();
+        let mut years = BTreeMap::new();
+        let mut months = BTreeMap::new();
+        let mut tags = HashMap::<&str, usize>::new();
+        let mut unresolved_media = 0;
+        let mut media_sizes = HashMap::new();
+        for post in posts {
+            *types.entry(post.r#type.name()).or_default() += 1;
+            if let Some(date) = post.common.parsed_date() {
+                *years.entry(date.format("%Y").to_string()).or_default() += 1;
+                *months.entry(date.format("%Y-%m").to_string()).or_default() += 1;
+            }
+            for tag in &post.common.tags {
+                *tags.entry(tag).or_default() += 1;
+            }
+            unresolved_media += post.unresolved_media();
+            for media in post.media() {
+                if let Some(path) = &media.path {
+                    media_sizes.insert(path, media.size.unwrap_or_default());
+                }
+            }
+        }
+        let media_files = media_sizes.len();
+        let media_bytes = media_sizes.values().sum();
+
+        let dates = posts.iter().filter_map(|post| post.common.parsed_date());
+        let (first_post, last_post) = match dates.minmax().into_option() {
+            Some((first, last)) => (
+                Some(first.format(DATE_FORMAT).to_string()),
+                Some(last.format(DATE_FORMAT).to_string()),
+            ),
+            None => (None, None),
+        };
+        let dated = years.values().sum::<usize>();
+        Self {
+            posts: posts.len(),
+            types,
+            years,
+            months,
+            undated: posts.len() - dated,
+            top_tags: tags
+                .into_iter()
+                .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)))
+                .take(TOP_TAGS)
+                .map(|(tag, count)| TagCount {
+                    tag: tag.to_string(),
+                    count,
+                })
+                .collect(),
+            media_files,
+            media_bytes,
+            unresolved_media,
+            first_post,
+            last_post,
+        }
+    }
+}
+
+/// Print the completeness of each blog
+pub fn print_stats(args: &Args, stats_args: &StatsArgs) -> anyhow::Result<()> {
+    let mut blogs = list_blogs(&args.path).context("Unable to read blog directory")?;
+    if !stats_args.blogs.is_empty() {
+        if let Some(missing) = stats_args
+            .blogs
+            .iter()
+            .find(|name| !blogs.iter().any(|blog| blog.name == **name))
+        {
+            bail!("Blog {} not found", missing);
+        }
+        blogs.retain(|blog| stats_args.blogs.contains(&blog.name));
+    }
+    for blog in blogs {
+        let completeness = match blog_completeness(args, &blog) {
+            Ok(completeness) => completeness,
+            Err(e) => {
+                log::error!("Unable to load {}: {:#}", blog.name, e);
+                continue;
+            }
+        };
+        match &blog.title {
+            Some(title) => println!("{} ({})", blog.name, title),
+            None => println!("{}", blog.name),
+        }
+        if let Some(last_download) = &blog.last_download {
+            println!("  Last downloaded: {}", last_download);
+        }
+        println!(
+            "  Posts: {}",
+            describe(
+                completeness.posts_present,
+                completeness.posts_online,
+                completeness.posts_missing_media
+            )
+        );
+        for (name, types) in &completeness.types {
+            if types.present > 0 || types.online.is_some_and(|online| online > 0) {
+                println!(
+                    "  {}: {}",
+                    name,
+                    describe(types.present, types.online, types.missing_media)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// e.g. `8,102 of 9,450 (85.7%), 312 with missing media`
+fn describe(present: u64, online: Option<u64>, missing_media: u64) -> String {
+    let mut text = thousands(present);
+    match online {
+        Some(online) if online > 0 => {
+            let percent = present as f64 / online as f64 * 100.0;
+            text += &format!(" of {} ({:.1}%)", thousands(online), percent);
+        }
+        _ => {}
+    }
+    if missing_media > 0 {
+        text += &format!(", {} with missing media", thousands(missing_media));
+    }
+    text
+}
+
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (idx, c) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
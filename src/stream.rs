@@ -0,0 +1,62 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use std::io::{self, BufWriter, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Stream what `write` writes on another thread as a response body, so that nothing needs to
+/// be buffered in memory or on disk
+pub fn spawn_body<F>(size: BodySize, write: F) -> ChannelBody
+where
+    F: FnOnce(BufWriter<ChannelWriter>) -> anyhow::Result<()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    std::thread::spawn(move || {
+        let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(sender.clone()));
+        if let Err(e) = write(writer) {
+            let _ = sender.blocking_send(Err(io::Error::other(e)));
+        }
+    });
+    ChannelBody { receiver, size }
+}
+
+/// Forwards written bytes to a [ChannelBody], blocking while the client catches up
+pub struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A response body fed from a writer on another thread
+pub struct ChannelBody {
+    receiver: mpsc::Receiver<io::Result<Bytes>>,
+    size: BodySize,
+}
+
+impl MessageBody for ChannelBody {
+    type Error = io::Error;
+
+    fn size(&self) -> BodySize {
+        self.size
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -1,13 +1,15 @@
-use crate::model::{Answer, Image, Post, PostCommon, PostType, Text, Video, UNKNOWN_FILE};
-use crate::utils::{create_file_url, BlogDir};
+use crate::blog_dir::{file_name, BlogDir};
+use crate::model::{Answer, Image, Media, MediaKind, Post, PostCommon, PostType, Text, Video};
+use crate::utils::{escape_html, unescape_html};
 use crate::MetadataType;
 use anyhow::{bail, Context};
+use itertools::Itertools;
 use lol_html::{element, RewriteStrSettings};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use scraper::{Html, Selector};
+use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::path::Path;
 
 type TextMap = HashMap<&'static str, String>;
 
@@ -91,19 +93,22 @@ const ANSWER_FIELDS: &[Field] = &[
 ];
 
 impl MetadataType {
-    /// Parse a text format post
-    pub fn parse_text(self, text: String, blog_dir: &BlogDir) -> anyhow::Result<Post> {
-        let text_fields = match self {
+    fn text_fields(self) -> &'static [Field] {
+        match self {
             MetadataType::Videos => VIDEO_FIELDS,
             MetadataType::Images => IMAGE_FIELDS,
             MetadataType::Texts => TEXT_FIELDS,
             MetadataType::Answers => ANSWER_FIELDS,
-        };
-        let mut map = read_text_into_map(text, text_fields);
+        }
+    }
+
+    /// Parse a text format post
+    pub fn parse_text(self, text: String, blog_dir: &BlogDir) -> anyhow::Result<Post> {
+        let mut map = read_text_into_map(text, self.text_fields());
         let common = PostCommon::from_text_map(&mut map)?;
         let specific = match self {
             MetadataType::Videos => {
-                PostType::Video(Video::from_text_map(&mut map, &blog_dir.path, common.id))
+                PostType::Video(Video::from_text_map(&mut map, blog_dir, common.id))
             }
             MetadataType::Images => PostType::Image(Image::from_text_map(&mut map, blog_dir)),
             MetadataType::Texts => PostType::Text(Text::from_text_map(&mut map, blog_dir)),
@@ -114,6 +119,89 @@ impl MetadataType {
             r#type: specific,
         })
     }
+
+    /// Convert a text format post into the equivalent JSON format post
+    /// The `downloaded-media-files` are filled in from the files that can be found on disk, and
+    /// the names media would have been saved as when it can't be found, so that it's still
+    /// listed as missing
+    pub fn convert_text(self, text: String, blog_dir: &BlogDir) -> anyhow::Result<Value> {
+        let mut map = read_text_into_map(text, self.text_fields());
+        let common = PostCommon::from_text_map(&mut map)?;
+        let mut json = json!({
+            "id": common.id.to_string(),
+            "date": common.date.unwrap_or_default(),
+            "post_url": common.post_url.unwrap_or_default(),
+            "tags": common.tags,
+        });
+        let fields = json.as_object_mut().unwrap();
+        if let Some(reblog_name) = map.remove(FIELD_REBLOG_NAME.field_name) {
+            fields.insert("reblogged-from-name".into(), reblog_name.into());
+        }
+        let media: Vec<String> = match self {
+            MetadataType::Videos => {
+                fields.insert("type".into(), "video".into());
+                let caption = map.remove(FIELD_VIDEO_CAPTION.field_name);
+                fields.insert("caption".into(), caption.into());
+                let player = map.get(FIELD_VIDEO_PLAYER.field_name).cloned();
+                let media = match video_file_name(&mut map) {
+                    Ok(filename) => vec![blog_dir.find_file(&filename).unwrap_or_else(|| {
+                        log::warn!("No file on disk named {} for post {}", filename, common.id);
+                        filename.clone()
+                    })],
+                    Err(e) => {
+                        log::warn!("Unable to find a video URL for post {}: {}", common.id, e);
+                        vec![]
+                    }
+                };
+                if let Some(player) = player {
+                    fields.insert("video-player".into(), player.into());
+                }
+                media
+            }
+            MetadataType::Images => {
+                fields.insert("type".into(), "photo".into());
+                let caption = map.remove(FIELD_PHOTO_CAPTION.field_name);
+                fields.insert("photo-caption".into(), caption.into());
+                if let Some(url) = map.get(FIELD_PHOTO_URL.field_name) {
+                    fields.insert("photo-url-1280".into(), url.as_str().into());
+                }
+                let urls = photo_urls(&mut map);
+                if urls.len() > 1 {
+                    let photos = urls
+                        .iter()
+                        .map(|url| json!({ "photo-url-1280": url }))
+                        .collect::<Vec<_>>();
+                    fields.insert("photos".into(), photos.into());
+                }
+                urls.iter()
+                    .map(|url| image_file_or_name(url, blog_dir))
+                    .collect()
+            }
+            MetadataType::Texts => {
+                fields.insert("type".into(), "regular".into());
+                let title = map.remove(FIELD_TITLE.field_name);
+                fields.insert("regular-title".into(), title.into());
+                let body = map.remove(FIELD_BODY.field_name).unwrap_or_default();
+                let media = body_image_urls(&body)
+                    .iter()
+                    .map(|url| image_file_or_name(url, blog_dir))
+                    .unique()
+                    .collect();
+                fields.insert("regular-body".into(), body.into());
+                media
+            }
+            MetadataType::Answers => {
+                // The text format doesn't separate the question from the answer, so the
+                // question is left out
+                fields.insert("type".into(), "answer".into());
+                let body = map.remove(FIELD_BODY.field_name).unwrap_or_default();
+                fields.insert("answer".into(), body.into());
+                vec![]
+            }
+        };
+        fields.insert("downloaded-media-files".into(), media.into());
+        Ok(json)
+    }
 }
 
 fn read_text_into_map(input: String, fields: &[Field]) -> TextMap {
@@ -180,62 +268,97 @@ impl PostCommon {
     }
 }
 
+/// The photo set URLs, or the single photo URL
+fn photo_urls(map: &mut TextMap) -> Vec<String> {
+    let mut urls = map
+        .remove(FIELD_PHOTO_SET_URLS.field_name)
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if urls.is_empty() {
+        if let Some(url) = map.remove(FIELD_PHOTO_URL.field_name) {
+            urls.push(url);
+        }
+    }
+    if urls.is_empty() {
+        log::warn!("Unable to find any photo URLs");
+    }
+    urls
+}
+
+/// The `src` of all images within a text post body
+fn body_image_urls(body: &str) -> Vec<String> {
+    static SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("img[src]").unwrap());
+    Html::parse_fragment(body)
+        .select(&SELECTOR)
+        .filter_map(|img| img.value().attr("src"))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
 impl Image {
     fn from_text_map(map: &mut TextMap, blog_dir: &BlogDir) -> Self {
-        let mut urls = map
-            .remove(FIELD_PHOTO_SET_URLS.field_name)
-            .unwrap_or_default()
-            .split_whitespace()
-            .map(ToOwned::to_owned)
-            .collect::<Vec<_>>();
-        if urls.is_empty() {
-            if let Some(url) = map.remove(FIELD_PHOTO_URL.field_name) {
-                urls.push(url);
-            }
-        }
-        if urls.is_empty() {
-            log::warn!("Unable to find any photo URLs");
-        }
+        let photos = photo_urls(map)
+            .iter()
+            .map(|url| {
+                Media::from_matches(
+                    MediaKind::Image,
+                    Some(url),
+                    image_search_prefix(url).ok(),
+                    image_urls(url, blog_dir),
+                )
+            })
+            .collect();
         Self {
-            photo_urls: urls
-                .iter()
-                .map(|u| rewrite_image_url(u, blog_dir))
-                .collect(),
+            photos,
             caption: map.remove(FIELD_PHOTO_CAPTION.field_name),
         }
     }
 }
 
+/// The video filename TumblThree would have saved the 'Video player' source as
+fn video_file_name(map: &mut TextMap) -> anyhow::Result<String> {
+    let player = map
+        .remove(FIELD_VIDEO_PLAYER.field_name)
+        .context("Missing 'Video player' field")?;
+    let fragment = Html::parse_fragment(&player);
+    static SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("source").unwrap());
+    let video = fragment
+        .select(&SELECTOR)
+        .next()
+        .context("Missing 'source' tag")?;
+    let src = video
+        .value()
+        .attr("src")
+        .context("Source element missing 'src' attribute")?;
+
+    static REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"/(tumblr_[a-zA-Z\d]+)").unwrap());
+    let captures = REGEX
+        .captures(src)
+        .context("Couldn't find a supported video URL")?;
+    Ok(format!("{}.mp4", captures.get(1).unwrap().as_str()))
+}
+
 impl Video {
-    fn from_text_map(map: &mut TextMap, blog_dir: &Path, post_id: u64) -> Self {
-        let url: anyhow::Result<_> = (|| {
-            let player = map
-                .remove(FIELD_VIDEO_PLAYER.field_name)
-                .context("Missing 'Video player' field")?;
-            let fragment = Html::parse_fragment(&player);
-            static SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("source").unwrap());
-            let video = fragment
-                .select(&SELECTOR)
-                .next()
-                .context("Missing 'source' tag")?;
-            let src = video
-                .value()
-                .attr("src")
-                .context("Source element missing 'src' attribute")?;
-
-            static REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"/(tumblr_[a-zA-Z\d]+)").unwrap());
-            let captures = REGEX
-                .captures(src)
-                .context("Couldn't find a supported video URL")?;
-            let filename = format!("{}.mp4", captures.get(1).unwrap().as_str());
-
-            Ok(create_file_url(blog_dir, filename.as_str()))
-        })();
-        if let Err(e) = &url {
-            log::warn!("Unable to find a video URL for post {}: {}", post_id, e);
-        }
+    fn from_text_map(map: &mut TextMap, blog_dir: &BlogDir, post_id: u64) -> Self {
+        let video = match video_file_name(map) {
+            Ok(filename) => {
+                let urls = blog_dir.find_file(&filename).map(|f| blog_dir.file_url(&f));
+                Media::from_matches(
+                    MediaKind::Video,
+                    Some(&filename),
+                    Some(&filename),
+                    urls.into_iter().collect(),
+                )
+            }
+            Err(e) => {
+                log::warn!("Unable to find a video URL for post {}: {}", post_id, e);
+                Media::missing(MediaKind::Video, None, format!("{:#}", e))
+            }
+        };
         Video {
-            url: url.ok(),
+            video: Some(Box::new(video)),
             caption: map.remove(FIELD_VIDEO_CAPTION.field_name),
         }
     }
@@ -244,28 +367,11 @@ impl Video {
 impl Text {
     fn from_text_map(map: &mut TextMap, blog_dir: &BlogDir) -> Self {
         let body = map.remove(FIELD_BODY.field_name).unwrap_or_default();
-
-        // A text post may have images within the body
-        // We must rewrite the body HTML to replace with local URLs
-        let element_content_handlers = vec![element!("img[src]", |el| {
-            let src = el.get_attribute("src").unwrap();
-            let replacement = rewrite_image_url(&src, blog_dir);
-            el.set_attribute("src", &replacement)?;
-            Ok(())
-        })];
-        let body = lol_html::rewrite_str(
-            &body,
-            RewriteStrSettings {
-                element_content_handlers,
-                ..RewriteStrSettings::default()
-            },
-        )
-        .unwrap();
-
+        let (body, media) = body_images(&body, blog_dir);
         Self {
             title: map.remove(FIELD_TITLE.field_name),
             body,
-            media_urls: vec![],
+            media,
         }
     }
 }
@@ -274,41 +380,102 @@ impl Answer {
     fn from_text_map(map: &mut TextMap) -> Self {
         Self {
             body: map.remove(FIELD_BODY.field_name),
+            media: vec![],
         }
     }
 }
 
-/// Rewrite an Tumblr image URL to a file on disk
+/// Find the file on disk for a Tumblr image URL
 /// This relies on the assumption that TumblThree wasn't configured to rewrite the file names
 /// Used for metadata when the `downloaded_media_files` feature wasn't available
 /// https://github.com/TumblThreeApp/TumblThree/commit/62373027d7b5d13d548be90104a5f265a719ed64
-fn rewrite_image_url(url: &str, blog_dir: &BlogDir) -> String {
-    let result = (|| -> anyhow::Result<_> {
-        let slash_idx = url.rfind('/').context("Unable to find '/' in url")? + 1;
-        let url_filename = &url[slash_idx..];
-        let mut search_prefix = url_filename;
-
-        // Work around for some images where the URL size suffix does not match
-        // the one on disk, e.g. _540.jpg vs _1280.jpg
-        if search_prefix.starts_with("tumblr_") {
-            if let Some(last_underscore_idx) = search_prefix.rfind('_') {
-                search_prefix = &search_prefix[..last_underscore_idx + 1];
-            }
+fn find_image_file(url: &str, blog_dir: &BlogDir) -> anyhow::Result<String> {
+    Ok(find_image_files(url, blog_dir)?.remove(0))
+}
+
+/// Like [find_image_file], but returns the name the image would have been saved as if there is
+/// no such file
+fn image_file_or_name(url: &str, blog_dir: &BlogDir) -> String {
+    find_image_file(url, blog_dir).unwrap_or_else(|e| {
+        log::warn!("Unable to find file for '{}': {}", url, e);
+        file_name(url.split(['?', '#']).next().unwrap_or_default()).to_string()
+    })
+}
+
+/// Like [find_image_file], but returns all matching files, best first
+fn find_image_files(url: &str, blog_dir: &BlogDir) -> anyhow::Result<Vec<String>> {
+    let url_filename = file_name(url);
+    let search_prefix = image_search_prefix(url)?;
+    let matches = blog_dir.find_files_starting_with(search_prefix);
+    let Some(matched) = matches.first() else {
+        bail!("No file on disk starting with: {}", search_prefix);
+    };
+    if file_name(matched) != url_filename {
+        log::warn!("Rewriting file {} to {}", url_filename, matched);
+    }
+    Ok(matches)
+}
+
+/// The file name prefix searched for a Tumblr image URL
+pub fn image_search_prefix(url: &str) -> anyhow::Result<&str> {
+    let slash_idx = url.rfind('/').context("Unable to find '/' in url")? + 1;
+    let mut search_prefix = &url[slash_idx..];
+
+    // Work around for some images where the URL size suffix does not match
+    // the one on disk, e.g. _540.jpg vs _1280.jpg
+    if search_prefix.starts_with("tumblr_") {
+        if let Some(last_underscore_idx) = search_prefix.rfind('_') {
+            search_prefix = &search_prefix[..last_underscore_idx + 1];
         }
+    }
+    Ok(search_prefix)
+}
 
-        if let Some(matched) = blog_dir.find_file_starting_with(search_prefix) {
-            if matched != url_filename {
-                log::warn!("Rewriting file {} to {}", url_filename, matched);
-            }
-            return Ok(create_file_url(&blog_dir.path, &matched));
+/// Rewrite the images within a post body to the files on disk matching them, and record them as
+/// inline media, so that images which couldn't be found are listed as missing like any other
+/// media. Images that couldn't be found keep their original URL
+/// Returns the rewritten body, and the images in the order they appear
+pub fn body_images(body: &str, blog_dir: &BlogDir) -> (String, Vec<Media>) {
+    let mut media = Vec::new();
+    let element_content_handlers = vec![element!("img[src]", |el| {
+        let src = unescape_html(&el.get_attribute("src").unwrap_or_default());
+        let image = Media {
+            alt_text: el.get_attribute("alt").map(|alt| unescape_html(&alt)),
+            inline: true,
+            ..Media::from_matches(
+                MediaKind::Image,
+                Some(&src),
+                image_search_prefix(&src).ok(),
+                image_urls(&src, blog_dir),
+            )
+        };
+        if let Some(url) = &image.url {
+            el.set_attribute("src", &escape_html(url))?;
         }
-        bail!("No file on disk starting with: {}", search_prefix);
-    })();
-    match result {
-        Ok(url) => url,
+        media.push(image);
+        Ok(())
+    })];
+    let body = lol_html::rewrite_str(
+        body,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::default()
+        },
+    )
+    .unwrap();
+    (body, media)
+}
+
+/// Rewrite an Tumblr image URL to the URLs of all matching files on disk, best first
+pub fn image_urls(url: &str, blog_dir: &BlogDir) -> Vec<String> {
+    match find_image_files(url, blog_dir) {
+        Ok(filenames) => filenames
+            .iter()
+            .map(|filename| blog_dir.file_url(filename))
+            .collect(),
         Err(e) => {
             log::warn!("Unable to rewrite URL '{}': {}", url, e);
-            String::from(UNKNOWN_FILE)
+            vec![]
         }
     }
 }
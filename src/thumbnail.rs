@@ -0,0 +1,117 @@
+//! Resized copies of images, so that pages of posts don't load every image at full resolution
+//! Thumbnails are cached on disk, keyed by the image's path, size and modification time
+
+use crate::blog::Blog;
+use crate::model::Thumbnail;
+use anyhow::Context;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+
+/// The widths thumbnails are generated at
+pub const WIDTHS: &[u32] = &[240, 480, 960];
+/// The image types thumbnails can be generated from
+const SUPPORTED_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+const JPEG_QUALITY: u8 = 80;
+
+/// The thumbnails of an image within a blog, by its detected MIME type, empty if it isn't a
+/// supported image
+pub fn thumbnails(blog: &Blog, file: &str, mime_type: &str) -> Vec<Thumbnail> {
+    if !SUPPORTED_TYPES.contains(&mime_type) {
+        return vec![];
+    }
+    WIDTHS
+        .iter()
+        .map(|&width| Thumbnail {
+            width,
+            url: format!("{}/{}/{}", blog.thumbnail_url, width, file),
+        })
+        .collect()
+}
+
+/// The directory thumbnails are cached in when none is given
+pub fn default_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("tumbl-three-viewer-thumbnails")
+}
+
+/// The path of a cached JPEG thumbnail of an image within a blog, generating it if it isn't
+/// cached, returns `None` if there is no such file
+/// Images narrower than the thumbnail are only re-encoded
+pub fn thumbnail(
+    blog: &Blog,
+    file: &str,
+    width: u32,
+    cache_dir: &Path,
+) -> anyhow::Result<Option<PathBuf>> {
+    let (Some(modified), Some(size)) = (blog.modified(file)?, blog.file_size(file)?) else {
+        return Ok(None);
+    };
+    let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    // The key must be the same across runs and builds, so the cache outlives the process
+    let key = format!(
+        "{}\0{}\0{}\0{}\0{}",
+        blog.path.display(),
+        file,
+        size,
+        modified.as_nanos(),
+        width
+    );
+    let digest = Sha256::digest(key.as_bytes());
+    let name = digest[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let cached = cache_dir.join(format!("{}.jpg", name));
+    if cached.is_file() {
+        return Ok(Some(cached));
+    }
+
+    let Some(data) = blog.read_file(file)? else {
+        return Ok(None);
+    };
+    // Animations are decoded to their first frame
+    let image =
+        image::load_from_memory(&data).with_context(|| format!("Unable to decode {}", file))?;
+    let image = if image.width() > width {
+        image.thumbnail(width, u32::MAX)
+    } else {
+        image
+    };
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).encode_image(&flatten(image))?;
+
+    // Written to a temporary file first, so that a partly written thumbnail is never served
+    fs::create_dir_all(cache_dir).context("Unable to create thumbnail cache directory")?;
+    static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+    let temp = cached.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp, out)?;
+    fs::rename(&temp, &cached)?;
+    log::debug!(
+        "Cached {} thumbnail of {} as {}",
+        width,
+        file,
+        cached.display()
+    );
+    Ok(Some(cached))
+}
+
+/// Blend any transparency onto a white background, as JPEG has no alpha channel
+fn flatten(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.into_rgb8();
+    }
+    let rgba = image.into_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
@@ -0,0 +1,224 @@
+//! Reads the official Tumblr blog export, which contains a HTML page per post in `posts/html`
+//! and the post media in `media`
+
+use crate::archive::{self, Archive};
+use crate::blog::Blog;
+use crate::model::{Image, Media, MediaKind, Post, PostCommon, PostType, Text, Video};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use lol_html::{element, RewriteStrSettings};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const POSTS_DIR: &str = "posts/html/";
+const MEDIA_DIR: &str = "media/";
+
+/// Whether a directory contains an extracted Tumblr export
+pub fn is_export_dir(path: &Path) -> bool {
+    path.join(POSTS_DIR).is_dir()
+}
+
+/// Whether an archive contains a Tumblr export
+pub fn is_export_archive(archive: &Archive) -> bool {
+    archive
+        .files()
+        .any(|file| file.starts_with(POSTS_DIR) && file.ends_with(".html"))
+}
+
+/// The post and media files within an export, either from a directory or an archive
+fn export_files(blog: &Blog) -> anyhow::Result<HashSet<String>> {
+    if blog.is_archive() {
+        return Ok(archive::open(&blog.path)?
+            .files()
+            .filter(|file| file.starts_with(POSTS_DIR) || file.starts_with(MEDIA_DIR))
+            .map(ToOwned::to_owned)
+            .collect());
+    }
+    let mut files = HashSet::new();
+    for dir in [POSTS_DIR, MEDIA_DIR] {
+        if let Ok(entries) = fs::read_dir(blog.path.join(dir)) {
+            files.extend(
+                entries
+                    .flatten()
+                    .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+                    .map(|e| format!("{}{}", dir, e.file_name().to_string_lossy())),
+            );
+        }
+    }
+    Ok(files)
+}
+
+/// Loads all posts from an export, sorted by id
+pub fn load(blog: &Blog) -> anyhow::Result<Vec<Post>> {
+    let files = export_files(blog)?;
+    let post_files = files
+        .iter()
+        .filter_map(|file| {
+            let id = file.strip_prefix(POSTS_DIR)?.strip_suffix(".html")?;
+            Some((id.parse::<u64>().ok()?, file.clone()))
+        })
+        .collect::<Vec<_>>();
+    let blog_name = blog
+        .name
+        .rsplit_once('.')
+        .filter(|_| blog.is_archive())
+        .map_or(blog.name.as_str(), |(name, _)| name);
+    let mut posts = Vec::with_capacity(post_files.len());
+    for (id, file) in post_files {
+        let data = blog.read_file(&file)?.context("Missing post file")?;
+        let html = String::from_utf8(data).context(file.clone())?;
+        let resolve = |src: &str| resolve_media(blog, &files, src);
+        let post = parse_post(id, blog_name, &html, resolve).context(file)?;
+        posts.push(post);
+    }
+    posts.sort_by_key(|p| p.common.id);
+    Ok(posts)
+}
+
+/// Resolve a media `src`, which is relative to the post HTML file, `None` if the file isn't
+/// within the export, such as remote media that was never downloaded
+fn resolve_media(blog: &Blog, files: &HashSet<String>, src: &str) -> Option<String> {
+    let file = media_path(src)?;
+    files.contains(&file).then(|| blog.file_url(&file))
+}
+
+/// The path within the export of a media `src`, `None` for remote media
+fn media_path(src: &str) -> Option<String> {
+    if src.starts_with("http://") || src.starts_with("https://") {
+        return None;
+    }
+    let mut parts = POSTS_DIR
+        .trim_end_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    for part in src.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn parse_post(
+    id: u64,
+    blog_name: &str,
+    html: &str,
+    resolve: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<Post> {
+    static TIMESTAMP: Lazy<Selector> = Lazy::new(|| Selector::parse("#timestamp").unwrap());
+    static TAG: Lazy<Selector> = Lazy::new(|| Selector::parse("#footer .tag").unwrap());
+    static TITLE: Lazy<Selector> = Lazy::new(|| Selector::parse("body h1").unwrap());
+    static MEDIA: Lazy<Selector> =
+        Lazy::new(|| Selector::parse("img[src], video[src], video source[src]").unwrap());
+
+    let document = Html::parse_document(html);
+    let text = |selector: &Selector| {
+        document
+            .select(selector)
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .collect::<Vec<_>>()
+    };
+    let date = text(&TIMESTAMP)
+        .first()
+        .and_then(|timestamp| parse_timestamp(timestamp))
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string());
+    let tags = text(&TAG)
+        .into_iter()
+        .map(|tag| tag.trim_start_matches('#').to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let title = text(&TITLE).into_iter().next();
+    let media = document
+        .select(&MEDIA)
+        .filter_map(|el| {
+            let kind = match el.value().name() {
+                "img" => MediaKind::Image,
+                _ => MediaKind::Video,
+            };
+            let src = el.value().attr("src")?;
+            let urls = resolve(src).into_iter().collect::<Vec<_>>();
+            if urls.is_empty() {
+                log::warn!("Unable to find file matching {} for post {}", src, id);
+            }
+            Some(Media::from_matches(
+                kind,
+                Some(src),
+                media_path(src).as_deref(),
+                urls,
+            ))
+        })
+        .collect::<Vec<_>>();
+    let is_kind = |kind| media.iter().all(|media| media.kind == kind);
+
+    let common = PostCommon {
+        id,
+        post_url: Some(format!("https://{}.tumblr.com/post/{}", blog_name, id)),
+        date,
+        tags,
+    };
+    // All media is listed separately from the body
+    let r#type = if media.len() == 1 && is_kind(MediaKind::Video) {
+        PostType::Video(Video {
+            video: media.into_iter().next().map(Box::new),
+            caption: Some(body_html(html, &["video"])?),
+        })
+    } else if !media.is_empty() && is_kind(MediaKind::Image) && title.is_none() {
+        PostType::Image(Image {
+            photos: media,
+            caption: Some(body_html(html, &["img", "figure"])?),
+        })
+    } else {
+        PostType::Text(Text {
+            title,
+            body: body_html(html, &["h1", "img", "video"])?,
+            media,
+        })
+    };
+    Ok(Post { common, r#type })
+}
+
+/// The HTML within the post body, excluding the footer and the given elements
+fn body_html(html: &str, remove: &[&str]) -> anyhow::Result<String> {
+    let mut element_content_handlers = vec![element!("#footer", |el| {
+        el.remove();
+        Ok(())
+    })];
+    for selector in remove {
+        element_content_handlers.push(element!(selector, |el| {
+            el.remove();
+            Ok(())
+        }));
+    }
+    let html = lol_html::rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::default()
+        },
+    )?;
+    static BODY: Lazy<Selector> = Lazy::new(|| Selector::parse("body").unwrap());
+    Ok(Html::parse_document(&html)
+        .select(&BODY)
+        .next()
+        .map(|body| body.inner_html().trim().to_string())
+        .unwrap_or_default())
+}
+
+/// Parse a timestamp from the post footer e.g. `July 4th, 2015 1:23pm`
+fn parse_timestamp(timestamp: &str) -> Option<NaiveDateTime> {
+    static ORDINAL: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d+)(st|nd|rd|th)").unwrap());
+    let timestamp = ORDINAL.replace(timestamp, "$1");
+    let date = NaiveDateTime::parse_from_str(&timestamp, "%B %d, %Y %I:%M%p").ok();
+    if date.is_none() {
+        log::warn!("Unable to parse timestamp {}", timestamp);
+    }
+    date
+}
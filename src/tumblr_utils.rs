@@ -0,0 +1,47 @@
+//! Reads backups made by tumblr-utils (`tumblr_backup`) with the `--json` option, which saves
+//! the API response for each post in `json` and the post media in `media`
+
+use crate::api_v2;
+use crate::blog::Blog;
+use crate::blog_dir::BlogDir;
+use crate::model::Post;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+const JSON_DIR: &str = "json";
+const MEDIA_DIR: &str = "media";
+
+/// Whether a directory contains a tumblr-utils backup
+pub fn is_backup_dir(path: &Path) -> bool {
+    path.join(JSON_DIR).is_dir()
+        && (path.join("posts").is_dir() || path.join("index.html").is_file())
+}
+
+/// Loads all posts from a backup, sorted by id, posts that can't be read are skipped unless
+/// `strict`
+pub fn load(blog: &Blog, strict: bool) -> anyhow::Result<Vec<Post>> {
+    let media_path = blog.path.join(MEDIA_DIR);
+    let media = if media_path.is_dir() {
+        BlogDir::new(&media_path)
+    } else {
+        BlogDir::from_files(media_path, BTreeSet::new(), None)
+    };
+    let mut posts = Vec::new();
+    for entry in fs::read_dir(blog.path.join(JSON_DIR))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let post = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(serde_json::from_str(&text)?))
+                .and_then(|json| api_v2::parse_post(json, &media));
+            match post {
+                Ok(post) => posts.push(post),
+                Err(e) if strict => return Err(e.context(path.display().to_string())),
+                Err(e) => log::warn!("Skipping post {}: {:#}", path.display(), e),
+            }
+        }
+    }
+    posts.sort_by_key(|p| p.common.id);
+    Ok(posts)
+}
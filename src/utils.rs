@@ -1,46 +1,141 @@
-use std::path::{Path, PathBuf};
-
-pub fn create_file_url(blog_dir: &Path, filename: &str) -> String {
-    let path = blog_dir.join(filename).to_string_lossy().to_string();
-    let path = path.replace(r"\\?\UNC\", "//");
-    let path = path.replace(r"\\?\", "");
-    let path = path.replace('\\', "/");
-    format!("file:///{}", path)
-}
+use crate::blog::Blog;
+use crate::model::Post;
+use chrono::{DateTime, NaiveDateTime};
+use itertools::Itertools;
+use lol_html::{element, RewriteStrSettings};
 
-pub struct BlogDir {
-    pub path: PathBuf,
-    pub files: Vec<String>,
+/// Replaces the URLs of files within a blog with URLs under a new base
+pub struct RebaseFileUrls {
+    local_prefix: String,
+    base_url: String,
 }
 
-impl BlogDir {
-    pub fn new(path: &Path) -> Self {
-        let list = std::fs::read_dir(path).expect("Unable to read blog directory");
-        let files = list
-            .into_iter()
-            .flatten()
-            .filter(|r| r.file_type().unwrap().is_file())
-            .map(|r| r.file_name().to_string_lossy().to_string())
-            .collect();
+impl RebaseFileUrls {
+    pub fn new(blog: &Blog, base_url: &str) -> Self {
         Self {
-            path: path.to_path_buf(),
-            files,
+            local_prefix: blog.file_url(""),
+            base_url: format!("{}/", base_url.trim_end_matches('/')),
         }
     }
 
-    pub fn find_file_starting_with(&self, starting_with: &str) -> Option<String> {
-        let matches = self
-            .files
-            .iter()
-            .filter(|f| f.starts_with(starting_with))
+    pub fn rebase(&self, text: &str) -> String {
+        text.replace(&self.local_prefix, &self.base_url)
+    }
+
+    /// The paths of the files within the blog referenced by a post: the files of its media, and
+    /// the `src` and `href` attributes within its body
+    pub fn post_files(&self, post: &Post) -> Vec<String> {
+        let mut files = post
+            .media()
+            .into_iter()
+            .filter_map(|media| {
+                media
+                    .path
+                    .clone()
+                    .or_else(|| self.file_path(media.url.as_deref()?))
+            })
             .collect::<Vec<_>>();
-        if matches.len() > 1 {
-            log::warn!(
-                "Found multiple matches for file search {}: {:?}",
-                starting_with,
-                matches
-            );
+        for body in post.bodies() {
+            let element_content_handlers = vec![element!("[src], [href]", |el| {
+                for name in ["src", "href"] {
+                    let url = el.get_attribute(name).map(|url| unescape_html(&url));
+                    files.extend(url.and_then(|url| self.file_path(&url)));
+                }
+                Ok(())
+            })];
+            let settings = RewriteStrSettings {
+                element_content_handlers,
+                ..RewriteStrSettings::default()
+            };
+            if let Err(e) = lol_html::rewrite_str(body, settings) {
+                log::warn!("Unable to parse body of post {}: {}", post.common.id, e);
+            }
         }
-        matches.first().map(ToString::to_string)
+        files.into_iter().unique().collect()
     }
+
+    /// The path of a file within the blog from its URL
+    pub fn file_path(&self, url: &str) -> Option<String> {
+        url.strip_prefix(&self.local_prefix)
+            .filter(|path| !path.is_empty())
+            .map(ToOwned::to_owned)
+    }
+}
+
+/// Parse a post date as written by TumblThree
+/// Newer metadata uses the API v2 format e.g. `2021-05-04 18:00:34 GMT`, older metadata may use
+/// the API v1 format e.g. `Tue, 04 May 2021 20:00:34`
+pub fn parse_post_date(date: &str) -> Option<NaiveDateTime> {
+    const FORMATS: &[&str] = &[
+        "%Y-%m-%d %H:%M:%S GMT",
+        "%Y-%m-%d %H:%M:%S",
+        "%a, %d %b %Y %H:%M:%S",
+    ];
+    let date = date.trim();
+    FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            DateTime::parse_from_rfc2822(date)
+                .or_else(|_| DateTime::parse_from_rfc3339(date))
+                .ok()
+                .map(|d| d.naive_utc())
+        })
+}
+
+/// Escape text for use within HTML or XML content and attribute values
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode the character references within HTML text or attribute values, such as those written
+/// by [escape_html]
+pub fn unescape_html(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        unescaped.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                reference => {
+                    let number = reference.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                unescaped.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Convert a tag or title into a lowercase URL slug
+pub fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .join("-")
 }
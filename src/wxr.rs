@@ -0,0 +1,242 @@
+use crate::model::{Post, PostType};
+use crate::utils::{escape_html as escape, slugify, RebaseFileUrls};
+use crate::{find_blog, sniff, Args};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use itertools::Itertools;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct WxrArgs {
+    /// Name of the blog to export
+    blog: String,
+    /// File to write the WXR to, defaults to `<blog>.xml`
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// URL the blog's media files will be uploaded to before importing,
+    /// defaults to referencing the local files
+    #[arg(long)]
+    media_base_url: Option<String>,
+}
+
+/// Export a blog to a WordPress eXtended RSS file
+pub fn export(args: &Args, wxr_args: &WxrArgs) -> anyhow::Result<()> {
+    let blog = find_blog(args, &wxr_args.blog)?;
+    let mut posts = blog.load()?;
+    sniff::describe_media(&blog, &mut posts);
+    let output = wxr_args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.xml", wxr_args.blog)));
+    let media = MediaUrls {
+        rebase: wxr_args
+            .media_base_url
+            .as_deref()
+            .map(|base_url| RebaseFileUrls::new(&blog, base_url)),
+    };
+    let mut xml = String::new();
+    write_wxr(&mut xml, &wxr_args.blog, &posts, &media)?;
+    fs::write(&output, xml).with_context(|| format!("Unable to write {}", output.display()))?;
+    log::info!("Exported {} posts to {}", posts.len(), output.display());
+    Ok(())
+}
+
+/// Media is referenced by local file URLs unless a base URL is given
+struct MediaUrls {
+    rebase: Option<RebaseFileUrls>,
+}
+
+impl MediaUrls {
+    fn rewrite(&self, html: &str) -> String {
+        match &self.rebase {
+            Some(rebase) => rebase.rebase(html),
+            None => html.to_string(),
+        }
+    }
+}
+
+fn write_wxr(out: &mut String, blog: &str, posts: &[Post], media: &MediaUrls) -> fmt::Result {
+    let link = format!("https://{}.tumblr.com", blog);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<rss version="2.0" xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:wfw="http://wellformedweb.org/CommentAPI/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:wp="http://wordpress.org/export/1.2/">"#
+    )?;
+    writeln!(out, "<channel>")?;
+    writeln!(out, "\t<title>{}</title>", escape(blog))?;
+    writeln!(out, "\t<link>{}</link>", escape(&link))?;
+    writeln!(out, "\t<description></description>")?;
+    writeln!(out, "\t<wp:wxr_version>1.2</wp:wxr_version>")?;
+    writeln!(
+        out,
+        "\t<wp:base_site_url>{}</wp:base_site_url>",
+        escape(&link)
+    )?;
+    writeln!(
+        out,
+        "\t<wp:base_blog_url>{}</wp:base_blog_url>",
+        escape(&link)
+    )?;
+    let tags = posts
+        .iter()
+        .flat_map(|p| p.common.tags.iter())
+        .unique()
+        .sorted();
+    for (idx, tag) in tags.enumerate() {
+        writeln!(
+            out,
+            "\t<wp:tag><wp:term_id>{}</wp:term_id><wp:tag_slug>{}</wp:tag_slug><wp:tag_name>{}</wp:tag_name></wp:tag>",
+            idx + 1,
+            escape(&slugify(tag)),
+            cdata(tag)
+        )?;
+    }
+
+    // WordPress post ids are assigned sequentially, with the attachments following the posts
+    let mut next_attachment_id = posts.len() + 1;
+    for (idx, post) in posts.iter().enumerate() {
+        let post_id = idx + 1;
+        let date = post.common.parsed_date();
+        write_post(out, blog, post_id, post, date, media)?;
+        for url in post.media_urls() {
+            write_attachment(out, next_attachment_id, post_id, url, date, media)?;
+            next_attachment_id += 1;
+        }
+    }
+
+    writeln!(out, "</channel>")?;
+    writeln!(out, "</rss>")
+}
+
+fn write_post(
+    out: &mut String,
+    blog: &str,
+    post_id: usize,
+    post: &Post,
+    date: Option<NaiveDateTime>,
+    media: &MediaUrls,
+) -> fmt::Result {
+    let title = post.title().unwrap_or_default();
+    let post_url = post.common.post_url.as_deref().unwrap_or_default();
+    writeln!(out, "\t<item>")?;
+    writeln!(out, "\t\t<title>{}</title>", escape(title))?;
+    writeln!(out, "\t\t<link>{}</link>", escape(post_url))?;
+    write_dates(out, date)?;
+    writeln!(out, "\t\t<dc:creator>{}</dc:creator>", cdata(blog))?;
+    writeln!(
+        out,
+        "\t\t<guid isPermaLink=\"false\">{}</guid>",
+        escape(post_url)
+    )?;
+    writeln!(out, "\t\t<description></description>")?;
+    writeln!(
+        out,
+        "\t\t<content:encoded>{}</content:encoded>",
+        cdata(&media.rewrite(&post.to_html()))
+    )?;
+    writeln!(out, "\t\t<excerpt:encoded><![CDATA[]]></excerpt:encoded>")?;
+    writeln!(out, "\t\t<wp:post_id>{}</wp:post_id>", post_id)?;
+    writeln!(out, "\t\t<wp:comment_status>closed</wp:comment_status>")?;
+    writeln!(out, "\t\t<wp:ping_status>closed</wp:ping_status>")?;
+    if let Some(slug) = post.common.slug() {
+        writeln!(out, "\t\t<wp:post_name>{}</wp:post_name>", cdata(slug))?;
+    }
+    writeln!(out, "\t\t<wp:status>publish</wp:status>")?;
+    writeln!(out, "\t\t<wp:post_parent>0</wp:post_parent>")?;
+    writeln!(out, "\t\t<wp:menu_order>0</wp:menu_order>")?;
+    writeln!(out, "\t\t<wp:post_type>post</wp:post_type>")?;
+    writeln!(out, "\t\t<wp:post_password></wp:post_password>")?;
+    writeln!(out, "\t\t<wp:is_sticky>0</wp:is_sticky>")?;
+    if let Some(format) = post_format(post) {
+        writeln!(
+            out,
+            "\t\t<category domain=\"post_format\" nicename=\"post-format-{}\">{}</category>",
+            format,
+            cdata(format)
+        )?;
+    }
+    for tag in &post.common.tags {
+        writeln!(
+            out,
+            "\t\t<category domain=\"post_tag\" nicename=\"{}\">{}</category>",
+            escape(&slugify(tag)),
+            cdata(tag)
+        )?;
+    }
+    write_meta(out, "tumblr_post_id", &post.common.id.to_string())?;
+    if !post_url.is_empty() {
+        write_meta(out, "tumblr_post_url", post_url)?;
+    }
+    writeln!(out, "\t</item>")
+}
+
+fn write_attachment(
+    out: &mut String,
+    attachment_id: usize,
+    parent_id: usize,
+    url: &str,
+    date: Option<NaiveDateTime>,
+    media: &MediaUrls,
+) -> fmt::Result {
+    let url = media.rewrite(url);
+    let filename = url.rsplit('/').next().unwrap_or_default();
+    writeln!(out, "\t<item>")?;
+    writeln!(out, "\t\t<title>{}</title>", escape(filename))?;
+    write_dates(out, date)?;
+    writeln!(
+        out,
+        "\t\t<guid isPermaLink=\"false\">{}</guid>",
+        escape(&url)
+    )?;
+    writeln!(out, "\t\t<wp:post_id>{}</wp:post_id>", attachment_id)?;
+    writeln!(out, "\t\t<wp:status>inherit</wp:status>")?;
+    writeln!(out, "\t\t<wp:post_parent>{}</wp:post_parent>", parent_id)?;
+    writeln!(out, "\t\t<wp:post_type>attachment</wp:post_type>")?;
+    writeln!(
+        out,
+        "\t\t<wp:attachment_url>{}</wp:attachment_url>",
+        cdata(&url)
+    )?;
+    writeln!(out, "\t</item>")
+}
+
+fn write_dates(out: &mut String, date: Option<NaiveDateTime>) -> fmt::Result {
+    // Dates recorded by TumblThree are in GMT
+    if let Some(date) = date {
+        writeln!(
+            out,
+            "\t\t<pubDate>{}</pubDate>",
+            date.format("%a, %d %b %Y %H:%M:%S +0000")
+        )?;
+        let date = date.format("%Y-%m-%d %H:%M:%S");
+        writeln!(out, "\t\t<wp:post_date>{}</wp:post_date>", date)?;
+        writeln!(out, "\t\t<wp:post_date_gmt>{}</wp:post_date_gmt>", date)?;
+    }
+    Ok(())
+}
+
+fn write_meta(out: &mut String, key: &str, value: &str) -> fmt::Result {
+    writeln!(
+        out,
+        "\t\t<wp:postmeta><wp:meta_key>{}</wp:meta_key><wp:meta_value>{}</wp:meta_value></wp:postmeta>",
+        cdata(key),
+        cdata(value)
+    )
+}
+
+/// Map the Tumblr post type onto a WordPress post format, standard posts have no format
+fn post_format(post: &Post) -> Option<&'static str> {
+    match &post.r#type {
+        PostType::Image(image) if image.photos.len() > 1 => Some("gallery"),
+        PostType::Image(_) => Some("image"),
+        PostType::Video(_) => Some("video"),
+        PostType::Text(_) => None,
+        PostType::Answer(_) => Some("aside"),
+    }
+}
+
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
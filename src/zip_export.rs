@@ -0,0 +1,91 @@
+use crate::blog::Blog;
+use crate::model::Post;
+use crate::stream::{spawn_body, ChannelBody};
+use crate::utils::{escape_html, RebaseFileUrls};
+use actix_web::body::BodySize;
+use itertools::Itertools;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Stream a ZIP of the posts and their media files as it is written
+pub fn stream(blog: Blog, posts: Vec<Post>) -> ChannelBody {
+    spawn_body(BodySize::Stream, move |out| {
+        write_zip(out, &blog, &posts)
+            .inspect_err(|e| log::error!("Unable to write ZIP for {}: {:#}", blog.name, e))
+    })
+}
+
+fn write_zip<W: Write>(out: W, blog: &Blog, posts: &[Post]) -> anyhow::Result<()> {
+    let rebase = RebaseFileUrls::new(blog, "media");
+    let compressed = SimpleFileOptions::default();
+    let stored = compressed.compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new_stream(out);
+
+    zip.start_file("posts.json", compressed)?;
+    zip.write_all(
+        rebase
+            .rebase(&serde_json::to_string_pretty(posts)?)
+            .as_bytes(),
+    )?;
+    zip.start_file("index.html", compressed)?;
+    zip.write_all(rebase.rebase(&render_index(&blog.name, posts)).as_bytes())?;
+
+    let file_names = posts
+        .iter()
+        .flat_map(|post| rebase.post_files(post))
+        .unique();
+    for file_name in file_names {
+        // Media files are already compressed
+        let (mut file, size) = match blog.open_file(&file_name) {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                log::warn!("Skipping missing media file {}", file_name);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Skipping media file {}: {:#}", file_name, e);
+                continue;
+            }
+        };
+        let large_file = size >= u32::MAX as u64;
+        zip.start_file(
+            format!("media/{}", file_name),
+            stored.large_file(large_file),
+        )?;
+        io::copy(&mut file, &mut zip)?;
+    }
+    zip.finish()?.into_inner().flush()?;
+    Ok(())
+}
+
+/// A standalone page displaying the posts, similar to the viewer
+fn render_index(blog: &str, posts: &[Post]) -> String {
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{}</title>\n</head>\n<body>",
+        escape_html(blog)
+    );
+    for post in posts {
+        let _ = writeln!(
+            html,
+            "<div class=\"post\" id=\"{}\">\n<p><a href=\"{}\">{}</a></p>\n{}",
+            post.common.id,
+            escape_html(post.common.post_url.as_deref().unwrap_or_default()),
+            escape_html(post.common.date.as_deref().unwrap_or_default()),
+            post.to_html()
+        );
+        if !post.common.tags.is_empty() {
+            let _ = writeln!(
+                html,
+                "<p>Tags: {}</p>",
+                escape_html(&post.common.tags.join(", "))
+            );
+        }
+        let _ = writeln!(html, "</div>");
+    }
+    let _ = writeln!(html, "</body>\n</html>");
+    html
+}